@@ -0,0 +1,100 @@
+//! This module contains the processing of a batch by the worker.
+//! The coalesced ranges of a batch are downloaded and their records processed concurrently, and an error
+//! only fails the entries concerned: a failed download fails the entries of its range, a failed record only itself.
+use std::future::Future;
+
+use futures_util::StreamExt;
+
+use crate::commoncrawl::CdxEntry;
+use crate::warc_ranges::CoalescedRange;
+
+/// An entry of a batch that could not be processed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedEntry {
+    /// Position of the entry in the batch.
+    pub index: usize,
+    pub reason: String,
+}
+
+/// The result of processing a batch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchReport {
+    /// The number of entries of the batch.
+    pub entries: usize,
+    /// The failed entries, ordered by their position in the batch.
+    pub failed: Vec<FailedEntry>,
+}
+
+impl BatchReport {
+    /// Whether every entry of a non-empty batch failed. This hints at an infrastructure problem
+    /// (e.g. the source, the extractor or the queue being unavailable) rather than at bad entries.
+    pub fn all_failed(&self) -> bool {
+        self.entries > 0 && self.failed.len() == self.entries
+    }
+
+    /// The failed entries as batches of a single entry each, so that they are retried individually.
+    pub fn retry_batches(&self, batch: &[CdxEntry]) -> Vec<Vec<CdxEntry>> {
+        self.failed.iter().map(|failed| vec![batch[failed.index].clone()]).collect()
+    }
+}
+
+/// Downloads the `ranges` of a batch of `entries` entries with up to `download_concurrency` concurrent calls of
+/// `download`, which returns the bytes of a whole range, and passes each record with its position in the batch
+/// to `process`, with up to `processing_concurrency` concurrent calls.
+pub async fn process_ranges<D, DF, P, PF>(
+    entries: usize,
+    ranges: Vec<CoalescedRange>,
+    download_concurrency: usize,
+    processing_concurrency: usize,
+    download: D,
+    process: P,
+) -> BatchReport
+where
+    D: Fn(CoalescedRange) -> DF,
+    DF: Future<Output = anyhow::Result<Vec<u8>>>,
+    P: Fn(usize, Vec<u8>) -> PF,
+    PF: Future<Output = anyhow::Result<()>>,
+{
+    let mut failed: Vec<FailedEntry> = futures_util::stream::iter(ranges)
+        .map(|range| {
+            let data = download(range.clone());
+            async move { split_range(&range, data.await) }
+        })
+        .buffer_unordered(download_concurrency)
+        .flat_map(futures_util::stream::iter)
+        .map(|(index, record)| {
+            let process = &process;
+            async move {
+                let result = match record {
+                    Ok(record) => process(index, record).await.map_err(|e| format!("{e:#}")),
+                    Err(reason) => Err(reason),
+                };
+                (index, result)
+            }
+        })
+        .buffer_unordered(processing_concurrency)
+        .filter_map(|(index, result)| async move { result.err().map(|reason| FailedEntry { index, reason }) })
+        .collect()
+        .await;
+
+    failed.sort_by_key(|failed| failed.index);
+    BatchReport { entries, failed }
+}
+
+/// Splits the downloaded bytes of a range into its records. If the download failed, all of them fail.
+fn split_range(range: &CoalescedRange, data: anyhow::Result<Vec<u8>>) -> Vec<(usize, Result<Vec<u8>, String>)> {
+    match data {
+        Ok(data) => range
+            .members
+            .iter()
+            .map(|member| {
+                let record = range.member_data(member, &data).map(<[u8]>::to_vec).map_err(|e| format!("{e:#}"));
+                (member.index, record)
+            })
+            .collect(),
+        Err(e) => {
+            let reason = format!("{e:#}");
+            range.members.iter().map(|member| (member.index, Err(reason.clone()))).collect()
+        }
+    }
+}
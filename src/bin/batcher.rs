@@ -25,19 +25,40 @@
 //! The URLs in the index files are sorted alpha-numerically.
 //!
 //! Once the batcher has downloaded (parts of) an index file, it will filter out URLs that are not in English or that did not return a 200 HTTP status code, batch them into groups whose size has a constant upper limit and push the messages containing these URls into a RabbitMQ queue.
+//!
+//! The filter can be changed with the `--status`, `--languages`, `--mime`, `--url-regex` and `--min-length`/`--max-length` arguments,
+//! or replaced entirely by a JSON filter expression passed with `--filter-config` (see [pipeline::cdx_filter]).
+//!
+//! Since the index is sorted by SURT, a part of the crawl (e.g. a domain or a TLD) can be selected with `--surt-prefix`
+//! or `--surt-from`/`--surt-to`; only the cdx chunks overlapping these ranges are downloaded (see [pipeline::surt]).
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use pipeline::commoncrawl::{download_and_store, CdxEntry, ClusterIdxEntry};
+use futures_util::{StreamExt, TryStreamExt};
+use pipeline::cdx_filter::{CdxFilter, LanguageMatch};
+use pipeline::checkpoint::BatcherCheckpoint;
+use pipeline::http_client::{HttpClient, HttpClientArgs};
+use pipeline::rate_limit::RateLimitArgs;
+use pipeline::retry::{with_retry, RetryArgs};
+use pipeline::source::{index_path, CrawlSource, COMMONCRAWL_BASE_URL};
+use pipeline::surt::{chunk_limit_range, in_ranges, select_chunks, SurtRange};
+use pipeline::crawl_merge::{merge_sorted, DedupKey, NewestCaptureDeduplicator};
+use pipeline::commoncrawl::{
+    load_collinfo, CdxEntry, ClusterIdxEntry, CrawlSelector, ParseErrorPolicy,
+    COLLINFO_URL,
+};
 use pipeline::{
-    commoncrawl::{download_and_unzip, parse_cdx_line, parse_cluster_idx},
+    commoncrawl::{parse_cdx_lines, parse_cluster_idx},
     rabbitmq::{
-        publish, rabbitmq_channel_with_queue, rabbitmq_connection, BATCH_SIZE, CC_QUEUE_NAME_BATCHES,
+        publish, rabbitmq_channel_with_queue, rabbitmq_confirm_select, rabbitmq_connection, BATCH_SIZE,
+        CC_QUEUE_NAME_BATCHES,
     },
     tracing_and_metrics::{run_metrics_server, setup_tracing},
 };
 use std::fs;
 use autometrics::autometrics;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use metrics::{counter, increment_counter};
 
 #[derive(Parser, Debug)]
@@ -45,19 +66,182 @@ use metrics::{counter, increment_counter};
 struct Args {
     /// For an explanation for why this file needs to be provided, please
     /// see Readme.md, section "Why do we download the cluster.idx file up front?".
+    /// The file is looked up (and downloaded to) `./data/<dataset>/<index>`.
     #[arg(short('i'), long("index"), default_value = "cluster.idx")]
     cluster_idx_filename: String,
 
-    /// The dataset to use; the index file should point to the same dataset or it will not work
-    #[arg(short('d'), long("dataset"), default_value = "CC-MAIN-2024-30")]
-    dataset: String,
+    /// Comma-separated list of datasets (crawls) to use. Every element is either a crawl id,
+    /// `latest` or a year like `2024`, selecting all crawls of that year.
+    /// If more than one crawl is selected, the index entries of all crawls are merged
+    /// and only the most recent capture of every URL is published (see `--dedup`).
+    #[arg(short('d'), long("dataset"), value_delimiter = ',', default_value = "CC-MAIN-2024-30")]
+    datasets: Vec<CrawlSelector>,
+
+    /// Where to read the crawl data from: an `http(s)://` mirror, an S3-compatible bucket given as
+    /// `s3+http(s)://user:password@host:port/bucket/prefix` or a local `file://` directory.
+    /// All of them need to have the same layout as the default location.
+    #[arg(long("source"), default_value = COMMONCRAWL_BASE_URL, value_parser = CrawlSource::parse)]
+    source: CrawlSource,
+
+    #[command(flatten)]
+    retry: RetryArgs,
+
+    #[command(flatten)]
+    http: HttpClientArgs,
+
+    #[command(flatten)]
+    rate_limit: RateLimitArgs,
+
+    /// URL or local path of the Common Crawl `collinfo.json` used to resolve and validate `--dataset`.
+    #[arg(long("collinfo"), default_value = COLLINFO_URL)]
+    collinfo: String,
+
+    /// Use the `--dataset` crawl ids as they are, without checking them against `collinfo.json`.
+    #[arg(long("no-crawl-validation"))]
+    no_crawl_validation: bool,
+
+    /// Print the crawls listed in `collinfo.json` and exit.
+    #[arg(long("list-crawls"))]
+    list_crawls: bool,
+
+    /// Which captures are considered duplicates when processing multiple crawls.
+    #[arg(long("dedup"), value_enum, default_value_t = DedupKey::Surt)]
+    dedup: DedupKey,
 
     /// This command line argument can be used to limit the number of chunks that should be processed.
-    /// If set, the batcher only processes so many lines from the provided cluster.idx file.
+    /// If set, the batcher only processes so many lines from the provided cluster.idx file(s).
     /// Otherwise, it processes all entries in the file.
+    /// With several crawls, the limit applies to the first crawl, and the others are cut at the same SURT key.
     #[arg(short('c'), long("chunks"), default_value_t = 1000, 
     value_parser = clap::value_parser!(u64).range(1..=1000))]
     num_cdx_chunks_to_process: u64,
+
+    /// The number of cdx chunks that are downloaded and decompressed concurrently, including the chunk
+    /// whose batches are being published. Chunks are still published in the order of the cluster.idx file.
+    /// With several crawls, the chunks are split evenly between the crawls, but every crawl gets at least one.
+    #[arg(short('p'), long("download-concurrency"), default_value_t = 4,
+    value_parser = clap::value_parser!(u64).range(1..=64))]
+    download_concurrency: u64,
+
+    /// Only process URLs whose SURT key starts with this prefix, e.g. `com,example)/` or `de,`.
+    /// Can be given multiple times; together with `--surt-from`/`--surt-to` the union of all ranges is processed.
+    #[arg(long("surt-prefix"))]
+    surt_prefixes: Vec<String>,
+
+    /// Only process URLs whose SURT key is greater than or equal to this key.
+    #[arg(long("surt-from"))]
+    surt_from: Option<String>,
+
+    /// Only process URLs whose SURT key is less than this key.
+    #[arg(long("surt-to"))]
+    surt_to: Option<String>,
+
+    /// Whether malformed lines in the cluster.idx and cdx files are skipped (and counted) or abort the batcher.
+    #[arg(long("on-parse-error"), value_enum, default_value_t = ParseErrorPolicy::Skip)]
+    on_parse_error: ParseErrorPolicy,
+
+    /// The file the batcher stores its progress in after every processed cluster.idx entry.
+    #[arg(long("checkpoint"), default_value = "./data/checkpoint.json")]
+    checkpoint_file: String,
+
+    /// Continue after the last cluster.idx entry recorded in the checkpoint file instead of starting from the top.
+    /// The `--chunks` limit applies to the chunks processed in this run.
+    /// The index file, filter and SURT ranges have to be the same as in the run that wrote the checkpoint.
+    /// Only supported when processing a single crawl.
+    #[arg(long("resume"))]
+    resume: bool,
+
+    /// A JSON file containing a filter expression for the index entries.
+    /// If set, the filter arguments below are ignored.
+    #[arg(long("filter-config"))]
+    filter_config: Option<String>,
+
+    /// Comma-separated list of accepted HTTP status codes.
+    #[arg(long("status"), value_delimiter = ',', default_value = "200")]
+    status: Vec<usize>,
+
+    /// Comma-separated list of accepted languages (ISO-639-3 codes as used by Common Crawl).
+    #[arg(long("languages"), value_delimiter = ',', default_value = "eng")]
+    languages: Vec<String>,
+
+    /// Whether the primary language or any of the detected languages has to match.
+    #[arg(long("language-match"), value_enum, default_value_t = LanguageMatch::Any)]
+    language_match: LanguageMatch,
+
+    /// Comma-separated list of accepted MIME types. All MIME types are accepted if not set.
+    #[arg(long("mime"), value_delimiter = ',')]
+    mime: Vec<String>,
+
+    /// Only index entries whose URL matches this regular expression are accepted.
+    #[arg(long("url-regex"))]
+    url_regex: Option<regex::Regex>,
+
+    /// Minimum length of the WARC record in bytes.
+    #[arg(long("min-length"))]
+    min_length: Option<usize>,
+
+    /// Maximum length of the WARC record in bytes.
+    #[arg(long("max-length"))]
+    max_length: Option<usize>,
+}
+
+impl Args {
+    /// The SURT ranges selected by `--surt-prefix`, `--surt-from` and `--surt-to`.
+    /// Empty if all URLs should be processed.
+    fn surt_ranges(&self) -> Vec<SurtRange> {
+        let mut ranges: Vec<_> = self
+            .surt_prefixes
+            .iter()
+            .map(|prefix| SurtRange::prefix(prefix))
+            .collect();
+        if self.surt_from.is_some() || self.surt_to.is_some() {
+            ranges.push(SurtRange {
+                start: self.surt_from.clone().unwrap_or_default(),
+                end: self.surt_to.clone(),
+            });
+        }
+        ranges
+    }
+
+    /// The hash of the settings selecting the published entries, stored in the checkpoint.
+    fn checkpoint_settings(&self, filter: &CdxFilter) -> Result<String> {
+        BatcherCheckpoint::settings_hash(&self.cluster_idx_filename, filter, &self.surt_ranges())
+    }
+
+    /// Builds the filter for index entries, either from the filter file or from the filter arguments.
+    fn cdx_filter(&self) -> Result<CdxFilter> {
+        if let Some(path) = &self.filter_config {
+            return CdxFilter::from_file(path);
+        }
+
+        let mut filters = vec![
+            CdxFilter::Status {
+                codes: self.status.clone(),
+            },
+            CdxFilter::Language {
+                languages: self.languages.clone(),
+                mode: self.language_match,
+            },
+        ];
+        if !self.mime.is_empty() {
+            filters.push(CdxFilter::Mime {
+                mimes: self.mime.clone(),
+            });
+        }
+        if let Some(pattern) = &self.url_regex {
+            filters.push(CdxFilter::UrlRegex {
+                pattern: pattern.clone(),
+            });
+        }
+        if self.min_length.is_some() || self.max_length.is_some() {
+            filters.push(CdxFilter::Length {
+                min: self.min_length,
+                max: self.max_length,
+            });
+        }
+
+        Ok(CdxFilter::All { filters })
+    }
 }
 
 #[tokio::main]
@@ -74,96 +258,342 @@ async fn main() {
 }
 
 async fn run(args: Args) -> Result<()> {
-    
+    // a single client for all downloads, so that connections are reused
+    let client = HttpClient::new(args.http.build()?, args.rate_limit.limiter()?);
+    if args.list_crawls {
+        for crawl in load_collinfo(&client, &args.collinfo).await? {
+            println!("{}\t{} - {}\t{}", crawl.id, crawl.from, crawl.to, crawl.name);
+        }
+        return Ok(());
+    }
+
+    let datasets = resolve_datasets(&client, &args).await?;
+    tracing::info!("Processing crawl(s) {}", datasets.join(", "));
+    let filter = args.cdx_filter()?;
+    tracing::info!("Selecting index entries with filter {:?}", filter);
+
     let rabbit_conn = rabbitmq_connection()
         .await
         .with_context(|| "Looks like rabbit is not available.")?;
     let (channel, _queue) = rabbitmq_channel_with_queue(&rabbit_conn, CC_QUEUE_NAME_BATCHES).await?;
+    // batches only count as published once the broker confirmed them
+    rabbitmq_confirm_select(&channel).await?;
+
+    if let [dataset] = datasets.as_slice() {
+        let resume_from = if args.resume {
+            BatcherCheckpoint::load(&args.checkpoint_file)?
+        } else {
+            None
+        };
+        if let Some(checkpoint) = &resume_from {
+            if &checkpoint.crawl != dataset {
+                anyhow::bail!(
+                    "Checkpoint {} belongs to crawl {}, not {}",
+                    args.checkpoint_file,
+                    checkpoint.crawl,
+                    dataset
+                );
+            }
+            if checkpoint.settings != args.checkpoint_settings(&filter)? {
+                anyhow::bail!(
+                    "Checkpoint {} was written with a different index file, filter or SURT range; \
+                    resume with the same settings or start from the top",
+                    args.checkpoint_file
+                );
+            }
+            tracing::info!(
+                "Resuming after cluster.idx entry {} ({}), {} batches already published",
+                checkpoint.cluster_id,
+                checkpoint.cdx_filename,
+                checkpoint.batches_published
+            );
+        }
 
-    // build index structure for further processing
-    let idx = obtain_index(&args.cluster_idx_filename, &args.dataset).await?;
+        // build index structure for further processing
+        let idx = obtain_index(&client, &args.cluster_idx_filename, dataset, &args).await?;
 
-    // process index
-    process_index(
-        &idx,
-        &args.dataset,
-        args.num_cdx_chunks_to_process as usize,
-        &channel,
-    )
-    .await?;
+        // process index
+        process_index(&client, &idx, dataset, &args, &filter, resume_from, &channel).await?;
+    } else {
+        if args.resume {
+            anyhow::bail!("--resume is only supported when processing a single crawl");
+        }
+
+        let mut idxs = Vec::new();
+        for dataset in &datasets {
+            idxs.push(obtain_index(&client, &args.cluster_idx_filename, dataset, &args).await?);
+        }
+        // `--chunks` counts the chunks of the first crawl; all crawls are cut at the same SURT key,
+        // so that the merge sees the same URLs of every crawl
+        let limit = chunk_limit_range(&idxs[0], args.num_cdx_chunks_to_process as usize);
+        if let Some(limit) = &limit {
+            tracing::info!("Processing the index entries before {:?} of all crawls", limit.end);
+            for idx in idxs.iter_mut() {
+                *idx = select_chunks(std::mem::take(idx), std::slice::from_ref(limit));
+            }
+        }
+
+        process_merged_indexes(&client, &idxs, &datasets, &args, &filter, limit.as_ref(), &channel).await?;
+    }
 
     Ok(())
 }
 
-async fn obtain_index(index_file_name: &str, dataset_name: &str) -> Result<Vec<ClusterIdxEntry>> {
-    let index_file_path = format!("./data/{}", index_file_name);
+/// Resolves the `--dataset` selectors into crawl ids, validating them against `collinfo.json`.
+async fn resolve_datasets(client: &HttpClient, args: &Args) -> Result<Vec<String>> {
+    if args.no_crawl_validation {
+        return args
+            .datasets
+            .iter()
+            .map(|selector| match selector {
+                CrawlSelector::Id(id) => Ok(id.clone()),
+                other => Err(anyhow::anyhow!(
+                    "`{}` can only be resolved without --no-crawl-validation",
+                    other
+                )),
+            })
+            .collect();
+    }
+
+    let crawls = load_collinfo(client, &args.collinfo).await?;
+    let mut datasets: Vec<String> = Vec::new();
+    for selector in &args.datasets {
+        for crawl in selector.select(&crawls)? {
+            if !datasets.contains(&crawl.id) {
+                datasets.push(crawl.id.clone());
+            }
+        }
+    }
+    Ok(datasets)
+}
+
+/// Reads (and if necessary downloads) the cluster.idx file of a crawl
+/// and returns the entries overlapping the selected SURT ranges.
+async fn obtain_index(
+    client: &HttpClient,
+    index_file_name: &str,
+    dataset_name: &str,
+    args: &Args,
+) -> Result<Vec<ClusterIdxEntry>> {
+    let index_file_path = format!("./data/{}/{}", dataset_name, index_file_name);
 
     // download file if not exists
     if !fs::exists(&index_file_path).unwrap_or(false) {
-        tracing::info!("Index file missing in ./data folder. Downloading...");
-        let index_file_url = format!(
-            "https://data.commoncrawl.org/cc-index/collections/{}/indexes/{}",
-            dataset_name, index_file_name
-        );
-
-        download_and_store(&index_file_url, &index_file_path).await?;
+        tracing::info!("Index file missing in ./data/{} folder. Downloading...", dataset_name);
+        let path = index_path(dataset_name, index_file_name);
+        with_retry(&args.retry.policy(), &path, || {
+            args.source.download_to_file(client, &path, &index_file_path)
+        })
+        .await?;
     }
 
-    let idx = fs::read_to_string(&index_file_path)
-        .with_context(|| format!("Failed to read idx file from {}", index_file_path))?
-        .lines()
-        .filter_map(parse_cluster_idx)
-        .collect::<Vec<_>>();
+    let content = fs::read_to_string(&index_file_path)
+        .with_context(|| format!("Failed to read idx file from {}", index_file_path))?;
+
+    let mut idx = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if let Some(entry) = args.on_parse_error.apply(parse_cluster_idx(line, i + 1), &index_file_path)? {
+            idx.push(entry);
+        }
+    }
+    let idx = select_chunks(idx, &args.surt_ranges());
 
     tracing::info!("{} index lines prepared for processing", idx.len());
 
     Ok(idx)
 }
 
+/// Processes the cluster.idx entries in order and stores a checkpoint after each entry
+/// whose batches have all been confirmed by RabbitMQ.
+/// Entries up to and including the one in `resume_from` are skipped.
 #[autometrics]
 async fn process_index(
-    idx: &Vec<ClusterIdxEntry>,
+    client: &HttpClient,
+    idx: &[ClusterIdxEntry],
     dataset: &str,
-    max_chunks_to_process: usize,
+    args: &Args,
+    filter: &CdxFilter,
+    resume_from: Option<BatcherCheckpoint>,
     channel: &lapin::Channel,
 ) -> Result<()> {
-    let mut num_cdx_chunks_processed = 0usize;
-    for cdx_chunk in idx {
-
-        let url = &format!(
-            "https://data.commoncrawl.org/cc-index/collections/{}/indexes/{}",
-            dataset, cdx_chunk.cdx_filename
-        );
-        
-        let content = download_and_unzip(url, cdx_chunk.cdx_offset, cdx_chunk.cdx_length, ).await?;
-        
-        let english_cdx_entries = String::from_utf8(content)?
-        .lines()
-        .map(parse_cdx_line)
-        .filter(select_only_english_cdx_entries)
-        .collect::<Vec<_>>();
-
-        for batch in english_cdx_entries.as_slice().chunks(BATCH_SIZE) {
+    let (start_after, mut batches_published) = match resume_from {
+        Some(checkpoint) => (Some(checkpoint.cluster_id), checkpoint.batches_published),
+        None => (None, 0),
+    };
+    let settings = args.checkpoint_settings(filter)?;
+
+    let chunks = idx
+        .iter()
+        .filter(|chunk| start_after.is_none_or(|id| chunk.cluster_id > id))
+        .take(args.num_cdx_chunks_to_process as usize);
+
+    // process up to `download_concurrency` chunks at once on separate tasks; `buffered` yields them
+    // in input order, so batches are published chunk by chunk in the order of the cluster.idx file.
+    // The chunk being published counts towards the limit: `buffered` only starts the next chunk
+    // once the producer of the previous one finished, as it is polled again only after that
+    let mut chunk_batches = futures_util::stream::iter(chunks)
+        .map(|cdx_chunk| {
+            let (batches, producer) = stream_cdx_chunk(client, cdx_chunk, dataset, args, filter.clone(), None);
+            std::future::ready((cdx_chunk, batches, producer))
+        })
+        .buffered(args.download_concurrency as usize);
+
+    while let Some((cdx_chunk, mut batches, producer)) = chunk_batches.next().await {
+        while let Some(batch) = batches.recv().await {
             counter!("index_chunks_processed", batch.len() as u64);
-            let batch = batch.to_vec();
             publish(channel, CC_QUEUE_NAME_BATCHES, &batch).await?;
+            batches_published += 1;
         }
-        
-        num_cdx_chunks_processed += 1;
+        // the channel is closed once the producer is done; check whether it finished successfully
+        producer.await.context("Chunk processing task failed")??;
 
-        if max_chunks_to_process == num_cdx_chunks_processed {
-            break;
+        BatcherCheckpoint {
+            crawl: dataset.to_string(),
+            cdx_filename: cdx_chunk.cdx_filename.clone(),
+            cluster_id: cdx_chunk.cluster_id,
+            batches_published,
+            settings: settings.clone(),
         }
+        .store(&args.checkpoint_file)?;
     }
 
     Ok(())
 }
 
-fn select_only_english_cdx_entries(e: &CdxEntry) -> bool {
-    if let Some(languages) = e.metadata.languages.as_ref() {
-        increment_counter!("batcher_cdx_entry_selected");
-        languages.contains("eng") && e.metadata.status == 200
-    } else {
-        false
+/// Merges the selected entries of several crawls in SURT order, keeps only the most recent capture
+/// of every URL and publishes the survivors in batches of [BATCH_SIZE].
+#[autometrics]
+async fn process_merged_indexes(
+    client: &HttpClient,
+    idxs: &[Vec<ClusterIdxEntry>],
+    datasets: &[String],
+    args: &Args,
+    filter: &CdxFilter,
+    limit: Option<&SurtRange>,
+    channel: &lapin::Channel,
+) -> Result<()> {
+    // all crawls are downloaded at the same time, so they share the download concurrency
+    let concurrency = (args.download_concurrency as usize / datasets.len()).max(1);
+    let streams = idxs
+        .iter()
+        .zip(datasets)
+        .map(|(idx, dataset)| Box::pin(crawl_entries(client, idx, dataset, args, filter, limit, concurrency)))
+        .collect();
+    let merged = merge_sorted(streams);
+    futures_util::pin_mut!(merged);
+
+    let mut deduplicator = NewestCaptureDeduplicator::new(args.dedup, datasets.to_vec());
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut ended = false;
+    while !ended {
+        let survivors = match merged.try_next().await? {
+            Some((source, entry)) => deduplicator.push(source, entry),
+            None => {
+                ended = true;
+                deduplicator.finish()
+            }
+        };
+        for entry in survivors {
+            batch.push(entry);
+            if batch.len() == BATCH_SIZE {
+                counter!("index_chunks_processed", batch.len() as u64);
+                publish(channel, CC_QUEUE_NAME_BATCHES, &batch).await?;
+                batch.clear();
+            }
+        }
+    }
+    if !batch.is_empty() {
+        counter!("index_chunks_processed", batch.len() as u64);
+        publish(channel, CC_QUEUE_NAME_BATCHES, &batch).await?;
     }
+
+    for (dataset, duplicates) in datasets.iter().zip(deduplicator.duplicates()) {
+        tracing::info!("Dropped {} duplicate captures from crawl {}", duplicates, dataset);
+    }
+
+    Ok(())
+}
+
+/// Returns the selected entries of a single crawl as a stream in SURT order, limited to `limit` if given.
+/// Like in [process_index], up to `concurrency` chunks are processed at once.
+fn crawl_entries<'a>(
+    client: &'a HttpClient,
+    idx: &'a [ClusterIdxEntry],
+    dataset: &'a str,
+    args: &'a Args,
+    filter: &'a CdxFilter,
+    limit: Option<&'a SurtRange>,
+    concurrency: usize,
+) -> impl futures_util::Stream<Item = Result<CdxEntry>> + 'a {
+    futures_util::stream::iter(idx)
+        .map(move |cdx_chunk| {
+            std::future::ready(stream_cdx_chunk(client, cdx_chunk, dataset, args, filter.clone(), limit.cloned()))
+        })
+        .buffered(concurrency)
+        .flat_map(|(batches, producer)| {
+            let batches = futures_util::stream::unfold(batches, |mut batches| async move {
+                batches.recv().await.map(|batch| (Ok(batch), batches))
+            });
+            // surface a failure of the producer after its last batch
+            let outcome = futures_util::stream::once(async move {
+                producer.await.context("Chunk processing task failed")?
+            })
+            .filter_map(|result| std::future::ready(result.err().map(Err)));
+            batches.chain(outcome)
+        })
+        .map_ok(|batch| futures_util::stream::iter(batch.into_iter().map(Ok)))
+        .try_flatten()
+}
+
+/// Spawns a task that downloads, decompresses, parses and filters a cdx chunk as a stream
+/// and sends the selected entries (within `limit`, if given) in batches of [BATCH_SIZE] to the returned channel.
+/// The channel is bounded, so at most a few batches per chunk are held in memory.
+fn stream_cdx_chunk(
+    client: &HttpClient,
+    cdx_chunk: &ClusterIdxEntry,
+    dataset: &str,
+    args: &Args,
+    filter: CdxFilter,
+    limit: Option<SurtRange>,
+) -> (mpsc::Receiver<Vec<CdxEntry>>, JoinHandle<Result<()>>) {
+    let path = index_path(dataset, &cdx_chunk.cdx_filename);
+    let crawl_source = args.source.clone();
+    let client = client.clone();
+    let retry_policy = args.retry.policy();
+    let (offset, length) = (cdx_chunk.cdx_offset, cdx_chunk.cdx_length);
+    let source = format!("{} at offset {}", cdx_chunk.cdx_filename, offset);
+    let on_parse_error = args.on_parse_error;
+    let ranges = args.surt_ranges();
+    let (sender, receiver) = mpsc::channel(2);
+
+    let producer = tokio::spawn(async move {
+        // the compressed chunk is downloaded as a whole, so that a connection failure while reading it is retried too
+        let lines = with_retry(&retry_policy, &source, || {
+            crawl_source.download_and_unzip_lines(&client, &path, offset, length)
+        })
+        .await?;
+        let batches = parse_cdx_lines(lines, on_parse_error, source)
+            .try_filter(|entry| {
+                // chunks at the boundaries of the SURT ranges also contain entries outside of them
+                let selected = in_ranges(&ranges, &entry.surt_url)
+                    && limit.as_ref().is_none_or(|limit| limit.contains(&entry.surt_url))
+                    && filter.matches(&entry.metadata);
+                if selected {
+                    increment_counter!("batcher_cdx_entry_selected");
+                }
+                std::future::ready(selected)
+            })
+            .try_chunks(BATCH_SIZE);
+        futures_util::pin_mut!(batches);
+
+        while let Some(batch) = batches.try_next().await.map_err(|e| e.1)? {
+            if sender.send(batch).await.is_err() {
+                // the receiver is gone, nobody is interested in the remaining batches
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    (receiver, producer)
 }
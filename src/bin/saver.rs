@@ -9,13 +9,12 @@
 use anyhow::{Context, Result};
 use clap::Parser;
 use futures_util::StreamExt;
-use lapin::options::{BasicAckOptions, BasicNackOptions};
 use minio::s3::args::{BucketExistsArgs, MakeBucketArgs};
-use minio::s3::client::{ClientBuilder};
+use minio::s3::client::{Client, ClientBuilder};
 use minio::s3::creds::StaticProvider;
 use minio::s3::http::BaseUrl;
 use pipeline::commoncrawl::CdxFileContext;
-use pipeline::rabbitmq::CC_QUEUE_NAME_STORE;
+use pipeline::rabbitmq::{rabbitmq_confirm_select, settle, DeliveryOutcome, RedeliveryArgs, CC_QUEUE_NAME_STORE};
 use pipeline::utility::{upload_file_to_minio};
 use pipeline::{
     rabbitmq::{rabbitmq_channel_with_queue, rabbitmq_connection, rabbitmq_consumer},
@@ -37,6 +36,9 @@ struct Args {
     /// The s3 bucket password
     #[arg(short('p'), long("password"))]
     s3_bucket_password: String,
+
+    #[command(flatten)]
+    redelivery: RedeliveryArgs,
 }
 
 #[tokio::main]
@@ -53,6 +55,8 @@ async fn main() {
 async fn run(file_processor_name: &str, args: Args) -> Result<()> {
     let rabbit_conn = rabbitmq_connection().await?;
     let (channel, _queue) = rabbitmq_channel_with_queue(&rabbit_conn, CC_QUEUE_NAME_STORE).await?;
+    // retries are published on this channel, and a message is only acknowledged once the broker has confirmed its retry
+    rabbitmq_confirm_select(&channel).await?;
     let mut consumer =
         rabbitmq_consumer(&channel, CC_QUEUE_NAME_STORE, file_processor_name).await?;
 
@@ -79,26 +83,12 @@ async fn run(file_processor_name: &str, args: Args) -> Result<()> {
             .await?;
     }
 
+    let redelivery_policy = args.redelivery.policy();
     while let Some(delivery) = consumer.next().await {
         match delivery {
             Ok(delivery) => {
-                let entry_item = serde_json::from_slice::<CdxFileContext>(&delivery.data);
-                if entry_item.is_err(){
-                    // item cannot be parsed, pushing it away
-                    tracing::warn!("Item cannot be parsed; rejected");
-                    delivery.nack(BasicNackOptions {multiple: false, requeue: false}).await?;
-                }
-
-                let entry = entry_item?;
-                let upload_result =
-                    upload_file_to_minio(&client, &entry, &args.s3_bucket).await;
-                if upload_result.is_err() {
-                    // negative-ack, with no requeue - it will not work, no matter what
-                    delivery.nack(BasicNackOptions {multiple: false, requeue: false}).await?;
-                }
-
-                // positive-ack
-                delivery.ack(BasicAckOptions::default()).await?;
+                let outcome = save_document(&client, &args.s3_bucket, &delivery.data).await;
+                settle(&channel, CC_QUEUE_NAME_STORE, &delivery, outcome, &redelivery_policy).await?;
             }
             Err(e) => {
                 tracing::warn!(err.msg = %e, err.details = ?e, "File processor failed to receive message from RabbitMQ. Reconnecting.");
@@ -108,4 +98,24 @@ async fn run(file_processor_name: &str, args: Args) -> Result<()> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Uploads the document of a message and decides what happens to the message.
+async fn save_document(client: &Client, s3_bucket: &str, data: &[u8]) -> DeliveryOutcome {
+    let entry = match serde_json::from_slice::<CdxFileContext>(data) {
+        Ok(entry) => entry,
+        Err(e) => {
+            // it will not work, no matter how often we try
+            tracing::warn!(err.msg = %e, "Item cannot be parsed; moving it to the dead-letter queue");
+            return DeliveryOutcome::DeadLetter;
+        }
+    };
+    match upload_file_to_minio(client, &entry, s3_bucket).await {
+        Ok(()) => DeliveryOutcome::Ack,
+        Err(e) => {
+            // e.g. MinIO is unavailable; uploads are idempotent, so trying again is safe
+            tracing::warn!(err.msg = %e, "Upload of {} failed; retrying later", entry.target_uri);
+            DeliveryOutcome::Requeue
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! The worker(s) pull(s) messages from the RabbitMQ queue and downloads the WARC files that contain the actual content of the URLs.
-//! Once the content has been downloaded, the worker extracts the text from the HTML file using the trafilatura Python package.
+//! Once the content has been downloaded, the worker extracts the text from the HTML file using the configured
+//! extractor, by default the trafilatura Python package.
 //!
 //! After having downloaded and extracted the text from the HTML file, the worker could apply some filters to the extracted text.
 //! We would also want to tokenize (for LLM training) the text and output it to a file.
@@ -8,47 +9,117 @@
 
 use anyhow::Result;
 use autometrics::autometrics;
+use clap::Parser;
 use futures_util::StreamExt;
 use lapin::options::BasicAckOptions;
 use metrics::{counter, increment_counter};
 use tokenizers::Tokenizer;
-use pipeline::commoncrawl::CdxFileContext;
-use pipeline::rabbitmq::{publish, CC_QUEUE_NAME_STORE};
+use pipeline::commoncrawl::{unzip, CdxFileContext};
+use pipeline::rabbitmq::{
+    dead_letter, delivery_attempt, publish, publish_retry, rabbitmq_confirm_select, settle, DeliveryOutcome,
+    CC_QUEUE_NAME_STORE,
+};
 use pipeline::{
-    commoncrawl::{download_and_unzip, CdxEntry},
+    batch_processing::{process_ranges, BatchReport, FailedEntry},
+    commoncrawl::CdxEntry,
+    extractor::{ExtractedDocument, Extractor, ExtractorArgs},
+    http_client::{HttpClient, HttpClientArgs},
+    rate_limit::RateLimitArgs,
     rabbitmq::{
-        rabbitmq_channel_with_queue, rabbitmq_connection, rabbitmq_consumer, CC_QUEUE_NAME_BATCHES,
+        rabbitmq_channel_with_queue, rabbitmq_connection, rabbitmq_consumer, RedeliveryArgs,
+        CC_QUEUE_NAME_BATCHES,
     },
+    retry::{with_retry, RetryArgs, RetryPolicy},
+    source::{CrawlSource, COMMONCRAWL_BASE_URL},
     tracing_and_metrics::{run_metrics_server, setup_tracing},
-    trafilatura,
+    warc_ranges::{coalesce_ranges, CoalesceArgs, CoalescedRange},
+    warc_record::{check_content_length, read_records, ResponseRecord, SkipReason},
 };
-use warc::WarcHeader;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Where to read the WARC files from: an `http(s)://` mirror, an S3-compatible bucket given as
+    /// `s3+http(s)://user:password@host:port/bucket/prefix` or a local `file://` directory.
+    /// All of them need to have the same layout as the default location.
+    #[arg(long("source"), default_value = COMMONCRAWL_BASE_URL, value_parser = CrawlSource::parse)]
+    source: CrawlSource,
+
+    #[command(flatten)]
+    retry: RetryArgs,
+
+    #[command(flatten)]
+    http: HttpClientArgs,
+
+    #[command(flatten)]
+    rate_limit: RateLimitArgs,
+
+    #[command(flatten)]
+    coalesce: CoalesceArgs,
+
+    /// The number of range requests of a batch that are downloaded concurrently.
+    #[arg(short('p'), long("download-concurrency"), default_value_t = 8,
+    value_parser = clap::value_parser!(u64).range(1..=64))]
+    download_concurrency: u64,
+
+    /// The number of downloaded records of a batch that are extracted and published concurrently.
+    #[arg(long("extraction-concurrency"), default_value_t = 4,
+    value_parser = clap::value_parser!(u64).range(1..=64))]
+    extraction_concurrency: u64,
+
+    #[command(flatten)]
+    extractor: ExtractorArgs,
+
+    /// Only log and count entries that fail instead of republishing them, each as a batch of its own,
+    /// to the retry queue of the batches queue.
+    #[arg(long("drop-failed-entries"))]
+    drop_failed_entries: bool,
+
+    #[command(flatten)]
+    redelivery: RedeliveryArgs,
+}
 
 #[tokio::main]
 async fn main() {
     setup_tracing();
     tokio::task::spawn(run_metrics_server(9000));
 
-    let run_result = run("worker").await;
+    let run_result = run("worker", Args::parse()).await;
     if let Err(e) = run_result {
         eprintln!("{e}");
         std::process::exit(1);
     }
 }
 
-async fn run(worker_name: &str) -> Result<()> {
+async fn run(worker_name: &str, args: Args) -> Result<()> {
     let rabbit_conn = rabbitmq_connection().await?;
     let (channel, _queue) =
         rabbitmq_channel_with_queue(&rabbit_conn, CC_QUEUE_NAME_BATCHES).await?;
     let (files_channel, _queue) =
         rabbitmq_channel_with_queue(&rabbit_conn, CC_QUEUE_NAME_STORE).await?;
+    // documents and retries are published on this channel; a batch is only acknowledged
+    // once the broker has confirmed them
+    rabbitmq_confirm_select(&files_channel).await?;
     let mut consumer = rabbitmq_consumer(&channel, CC_QUEUE_NAME_BATCHES, worker_name).await?;
     let tokenizer = Tokenizer::from_pretrained("bert-base-cased", None).unwrap();
+    let retry_policy = args.retry.policy();
+    let redelivery_policy = args.redelivery.policy();
+    // a single client for all downloads, so that connections are reused across batches
+    let client = HttpClient::new(args.http.build()?, args.rate_limit.limiter()?);
+    let extractor = args.extractor.build()?;
 
     while let Some(delivery) = consumer.next().await {
         match delivery {
             Ok(delivery) => {
-                let batch = serde_json::from_slice::<Vec<CdxEntry>>(&delivery.data)?;
+                let batch = match serde_json::from_slice::<Vec<CdxEntry>>(&delivery.data) {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        tracing::error!(err.msg = %e, "Rejecting a batch that is not a list of cdx entries");
+                        increment_counter!("worker_rejected_batches");
+                        dead_letter(CC_QUEUE_NAME_BATCHES, &delivery).await?;
+                        continue;
+                    }
+                };
                 let batch_len =  batch.len();
                 
                 tracing::info!(
@@ -60,10 +131,22 @@ async fn run(worker_name: &str) -> Result<()> {
                 counter!("worker_received_batch_total", batch_len as u64);
                 increment_counter!("worker_received_batch_count");
 
-                for entry in batch {
-                    process_index_entry(entry, &files_channel, &tokenizer).await?
+                let report = process_batch(&batch, &args, &client, &retry_policy, extractor.as_ref(), &files_channel, &tokenizer).await;
+                log_failed_entries(&batch, &report);
+                if report.all_failed() {
+                    // most likely the source, the extractor or the queue is unavailable, so the whole batch is tried again
+                    tracing::warn!("All entries of the batch failed; requeueing it");
+                    settle(&files_channel, CC_QUEUE_NAME_BATCHES, &delivery, DeliveryOutcome::Requeue, &redelivery_policy).await?;
+                    continue;
                 }
 
+                // failed entries do not keep the rest of the batch from being acknowledged
+                if !args.drop_failed_entries {
+                    let attempt = delivery_attempt(&delivery.properties) + 1;
+                    for retry_batch in report.retry_batches(&batch) {
+                        publish_retry(&files_channel, CC_QUEUE_NAME_BATCHES, &retry_batch, attempt, &redelivery_policy).await?;
+                    }
+                }
                 delivery.ack(BasicAckOptions::default()).await?;
             }
             Err(e) => {
@@ -76,82 +159,147 @@ async fn run(worker_name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Downloads the records of a batch with up to `download_concurrency` concurrent requests
+/// and feeds them to up to `extraction_concurrency` concurrent extractions.
+/// An error only affects the entries concerned, which are reported with the reason.
 #[autometrics]
-async fn process_index_entry(entry: CdxEntry, channel: &lapin::Channel, tokenizer: &Tokenizer) -> Result<()> {
-    let url = &format!("https://data.commoncrawl.org/{}", entry.metadata.filename);
-    let data = download_and_unzip(url, entry.metadata.offset, entry.metadata.length).await?;
+async fn process_batch(
+    batch: &[CdxEntry],
+    args: &Args,
+    client: &HttpClient,
+    retry_policy: &RetryPolicy,
+    extractor: &dyn Extractor,
+    channel: &lapin::Channel,
+    tokenizer: &Tokenizer,
+) -> BatchReport {
+    // one request per group of nearby records instead of one per entry
+    let ranges = coalesce_ranges(batch, args.coalesce.max_gap, args.coalesce.max_bytes);
+    counter!("worker_range_requests", ranges.len() as u64);
+
+    process_ranges(
+        batch.len(),
+        ranges,
+        args.download_concurrency as usize,
+        args.extraction_concurrency as usize,
+        |range| download_range(range, &args.source, client, retry_policy),
+        |index, record| async move { process_record(&batch[index], &record, extractor, channel, tokenizer).await },
+    )
+    .await
+}
+
+/// Unzips a downloaded record and processes it.
+async fn process_record(
+    entry: &CdxEntry,
+    record: &[u8],
+    extractor: &dyn Extractor,
+    channel: &lapin::Channel,
+    tokenizer: &Tokenizer,
+) -> Result<()> {
+    // every record is a gzip member of its own
+    let data = unzip(record)?;
     counter!("worker_downloaded_data", data.len() as u64);
+    process_index_entry(entry, &data, extractor, channel, tokenizer).await
+}
 
-    for warc_entry in warc::WarcReader::new(data.as_slice()).iter_records() {
-        let warc_entry = warc_entry?;
+/// Logs and counts the failed entries of a batch.
+fn log_failed_entries(batch: &[CdxEntry], report: &BatchReport) {
+    if report.failed.is_empty() {
+        return;
+    }
+    tracing::warn!("{} of {} entries of the batch failed", report.failed.len(), report.entries);
+    counter!("worker_failed_entries", report.failed.len() as u64);
 
-        if warc_entry.header(WarcHeader::WarcType).unwrap() != "response" {
-            continue;
-        }
+    for FailedEntry { index, reason } in &report.failed {
+        let entry = &batch[*index];
+        tracing::warn!(url = %entry.metadata.url, filename = %entry.metadata.filename, "Failed to process entry: {}", reason);
+    }
+}
 
-        let target_uri = warc_entry.header(WarcHeader::TargetURI).unwrap();
+/// Downloads a coalesced range and returns its (still compressed) bytes.
+async fn download_range(
+    range: CoalescedRange,
+    source: &CrawlSource,
+    client: &HttpClient,
+    retry_policy: &RetryPolicy,
+) -> Result<Vec<u8>> {
+    with_retry(retry_policy, &range.filename, || {
+        source.download(client, &range.filename, range.offset, range.length)
+    })
+    .await
+}
+
+#[autometrics]
+async fn process_index_entry(
+    entry: &CdxEntry,
+    data: &[u8],
+    extractor: &dyn Extractor,
+    channel: &lapin::Channel,
+    tokenizer: &Tokenizer,
+) -> Result<()> {
+    for record in read_records(data, entry.metadata.charset.as_deref()) {
+        let ResponseRecord { target_uri, html } = match record {
+            Ok(record) => record,
+            Err(reason) => {
+                skip(&entry.metadata.url, reason);
+                continue;
+            }
+        };
         tracing::info!("Successfully read WARC entry with URL {}", target_uri);
 
-        let raw_content = String::from_utf8_lossy(warc_entry.body());
-        extract_and_process_content(&entry, &raw_content, channel, &target_uri, tokenizer).await?
+        extract_and_process_content(entry, html, extractor, channel, &target_uri, tokenizer).await?
     }
 
     Ok(())
 }
 
+/// Counts and logs a record that does not result in a stored document.
+fn skip(url: &str, reason: SkipReason) {
+    tracing::debug!("Skipping WARC entry with URL {}: {}", url, reason);
+    increment_counter!("worker_skipped_records", "reason" => reason.label());
+}
+
 async fn extract_and_process_content(
     entry: &CdxEntry,
-    raw_content: &str,
+    html: String,
+    extractor: &dyn Extractor,
     channel: &lapin::Channel,
     target_uri: &str,
     tokenizer: &Tokenizer
 ) -> Result<()> {
-    let html_begin_index = raw_content.find("\n\n");
-    let Some(html_begin_index) = html_begin_index else {
-        // we ignore content that is not valid HTML
-        tracing::debug!("Failed to find HTML content in WARC entry");
-        return Ok(());
-    };
-
     tracing::debug!(
-        "First 1000 characters of raw content: {}",
-        &raw_content[..1000]
+        "First 1000 characters of HTML content: {}",
+        html.chars().take(1000).collect::<String>()
     );
     increment_counter!("worker_doc_processed");
 
-    let content = trafilatura::extract(&raw_content[html_begin_index..])?;
-
-    if let Some(content) = content {
-        let len = content.len();
-
-        tracing::debug!("Extracted content: {}", &content);
-        
-        if !(500..=1000000).contains(&len) {
-            tracing::debug!("Extracted content of length {}, which is outside the allowed range", len);
-            return Ok(());
-        }
-        else {
-            tracing::info!("Content length is {}; content will be transmitted for further processing", len);
-        }
+    let Some(ExtractedDocument { text: content, metadata }) = extractor.extract(html).await? else {
+        skip(target_uri, SkipReason::NoContent);
+        return Ok(());
+    };
 
-        // tokenize
-        let tokens = tokenize(&content, tokenizer).unwrap_or(Vec::new());
-        let file_content_to_save = CdxFileContext {
-            content: content,
-            filename: entry.metadata.filename.clone(),
-            target_uri: target_uri.to_string(),
-            tokens: tokens
-        };
-        publish(channel, CC_QUEUE_NAME_STORE, &file_content_to_save).await?;
-    } else {
-        tracing::warn!("Failed to extract content from WARC entry");
+    tracing::debug!("Extracted content: {}", &content);
+    if let Err(reason) = check_content_length(&content) {
+        skip(target_uri, reason);
+        return Ok(());
     }
+    tracing::info!("Content length is {}; content will be transmitted for further processing", content.len());
+
+    // tokenize
+    let tokens = tokenize(&content, tokenizer).unwrap_or_default();
+    let file_content_to_save = CdxFileContext {
+        content,
+        filename: entry.metadata.filename.clone(),
+        target_uri: target_uri.to_string(),
+        tokens,
+        metadata,
+    };
+    publish(channel, CC_QUEUE_NAME_STORE, &file_content_to_save).await?;
 
     Ok(())
 }
 
 fn tokenize(content: &str, tokenizer: &Tokenizer) -> Result<Vec<String>> {
-    let encoding = tokenizer.encode(content, false).unwrap();
+    let encoding = tokenizer.encode(content, false).map_err(|e| anyhow::anyhow!(e))?;
     let result = encoding.get_tokens();
     Ok(result.to_vec())
 }
\ No newline at end of file
@@ -0,0 +1,122 @@
+//! This module contains composable filters over [CdxMetadata].
+//! The batcher uses them to decide which index entries are passed on to the worker(s).
+//!
+//! Filters can be built in code or deserialized from a JSON file, e.g.:
+//!
+//! ```json
+//! {
+//!     "type": "all",
+//!     "filters": [
+//!         { "type": "status", "codes": [200, 304] },
+//!         { "type": "language", "languages": ["deu"], "mode": "primary" },
+//!         { "type": "not", "filter": { "type": "url_regex", "pattern": "/robots\\.txt$" } }
+//!     ]
+//! }
+//! ```
+use std::fs;
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::commoncrawl::CdxMetadata;
+
+/// How the `languages` field of a [CdxMetadata] is matched.
+/// Common Crawl lists detected languages ordered by confidence, e.g. `"ind,eng"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LanguageMatch {
+    /// Only the first (most confident) language is considered.
+    Primary,
+    /// Any of the detected languages may match.
+    #[default]
+    Any,
+}
+
+/// A predicate over [CdxMetadata]. Leaf filters inspect a single field,
+/// `all`, `any` and `not` combine other filters.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CdxFilter {
+    /// Accepts entries whose HTTP status is one of `codes`.
+    Status { codes: Vec<usize> },
+    /// Accepts entries whose detected languages contain one of `languages`.
+    /// Entries without language information are rejected.
+    Language {
+        languages: Vec<String>,
+        #[serde(default)]
+        mode: LanguageMatch,
+    },
+    /// Accepts entries whose MIME type is one of `mimes` (case-insensitive).
+    /// Entries without a MIME type are rejected.
+    Mime { mimes: Vec<String> },
+    /// Accepts entries whose URL matches `pattern`.
+    UrlRegex {
+        #[serde(with = "regex_serde")]
+        pattern: Regex,
+    },
+    /// Accepts entries whose record length lies within `min..=max`.
+    Length { min: Option<usize>, max: Option<usize> },
+    /// Accepts entries accepted by every filter in `filters`.
+    All { filters: Vec<CdxFilter> },
+    /// Accepts entries accepted by at least one filter in `filters`.
+    Any { filters: Vec<CdxFilter> },
+    /// Accepts entries rejected by `filter`.
+    Not { filter: Box<CdxFilter> },
+}
+
+impl CdxFilter {
+    /// Returns true if the entry described by `metadata` passes this filter.
+    pub fn matches(&self, metadata: &CdxMetadata) -> bool {
+        match self {
+            CdxFilter::Status { codes } => codes.contains(&metadata.status),
+            CdxFilter::Language { languages, mode } => {
+                let Some(detected) = metadata.languages.as_deref() else {
+                    return false;
+                };
+                let mut detected = detected.split(',').map(str::trim);
+                match mode {
+                    LanguageMatch::Primary => detected
+                        .next()
+                        .is_some_and(|primary| languages.iter().any(|l| l == primary)),
+                    LanguageMatch::Any => detected.any(|d| languages.iter().any(|l| l == d)),
+                }
+            }
+            CdxFilter::Mime { mimes } => metadata
+                .mime
+                .as_deref()
+                .is_some_and(|mime| mimes.iter().any(|m| m.eq_ignore_ascii_case(mime))),
+            CdxFilter::UrlRegex { pattern } => pattern.is_match(&metadata.url),
+            CdxFilter::Length { min, max } => {
+                min.is_none_or(|min| metadata.length >= min)
+                    && max.is_none_or(|max| metadata.length <= max)
+            }
+            CdxFilter::All { filters } => filters.iter().all(|f| f.matches(metadata)),
+            CdxFilter::Any { filters } => filters.iter().any(|f| f.matches(metadata)),
+            CdxFilter::Not { filter } => !filter.matches(metadata),
+        }
+    }
+
+    /// Reads a JSON-encoded filter expression from `path`.
+    pub fn from_file(path: &str) -> anyhow::Result<CdxFilter> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read filter file from {}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse filter file {}", path))
+    }
+}
+
+/// (De-)serializes a [Regex] as its source pattern.
+mod regex_serde {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,65 @@
+//! This module contains the checkpoint the batcher persists after every processed cluster.idx entry,
+//! so that a restarted batcher can continue where the previous run stopped.
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::cdx_filter::CdxFilter;
+use crate::surt::SurtRange;
+use crate::utility::calculate_hash;
+
+/// Progress of the batcher through the cluster.idx file of a crawl.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct BatcherCheckpoint {
+    /// The crawl that is being processed, e.g. `CC-MAIN-2024-30`.
+    pub crawl: String,
+    /// The cdx file of the last fully processed cluster.idx entry.
+    pub cdx_filename: String,
+    /// The id of the last fully processed cluster.idx entry.
+    pub cluster_id: usize,
+    /// The number of batches published so far, summed over all runs.
+    pub batches_published: usize,
+    /// Hash of the settings that select the published entries, see [BatcherCheckpoint::settings_hash].
+    /// A checkpoint is only resumed with the same settings, as the recorded progress means nothing otherwise.
+    #[serde(default)]
+    pub settings: String,
+}
+
+impl BatcherCheckpoint {
+    /// Reads the checkpoint stored at `path`.
+    /// Returns `Ok(None)` if there is no checkpoint yet.
+    pub fn load(path: &str) -> anyhow::Result<Option<BatcherCheckpoint>> {
+        if !fs::exists(path).with_context(|| format!("Failed to look up checkpoint {}", path))? {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read checkpoint from {}", path))?;
+        let checkpoint = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse checkpoint {}", path))?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Hashes the settings that select which entries of a crawl are published:
+    /// the cluster.idx file, the filter and the SURT ranges.
+    pub fn settings_hash(cluster_idx_filename: &str, filter: &CdxFilter, surt_ranges: &[SurtRange]) -> anyhow::Result<String> {
+        let settings = serde_json::to_string(&(cluster_idx_filename, filter, surt_ranges))?;
+        Ok(calculate_hash(&settings))
+    }
+
+    /// Writes the checkpoint to `path`.
+    /// The checkpoint is written to a temporary file first and then renamed,
+    /// so that a crash never leaves a half-written checkpoint behind.
+    pub fn store(&self, path: &str) -> anyhow::Result<()> {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write checkpoint to {}", tmp_path))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to move checkpoint to {}", path))?;
+        Ok(())
+    }
+}
@@ -1,14 +1,25 @@
 //! This module contains helper functions and structs for de-serializing CommonCrawl-specific data structures.
 use std::io::{Read, Write};
 use std::fs::{File};
+use std::num::ParseIntError;
+use std::time::{Duration, SystemTime};
 use anyhow::Context;
-use autometrics::autometrics;
+use async_compression::tokio::bufread::GzipDecoder;
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use metrics::increment_counter;
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::deserialize_number_from_string;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_util::io::StreamReader;
 use tracing::info;
 
+use crate::extractor::DocumentMetadata;
+use crate::http_client::HttpClient;
+
 /// Metadata for a crawled URL.
 /// We use this metadata in the batcher to filter URLs before passing them on to the worker(s).
+/// Fields that are not known to this struct are kept in `extra`.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CdxMetadata {
     pub url: String,
@@ -19,11 +30,65 @@ pub struct CdxMetadata {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub offset: usize,
     pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub languages: Option<String>,
+    /// MIME type as sent by the server in the `Content-Type` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// MIME type as detected by Common Crawl from the content.
+    #[serde(rename = "mime-detected", default, skip_serializing_if = "Option::is_none")]
+    pub mime_detected: Option<String>,
+    /// Base32-encoded SHA-1 digest of the payload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+    /// Target of a redirect, only set for 3xx responses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<String>,
+    /// Reason why the payload was truncated while crawling (`length`, `time` or `disconnect`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<String>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Error for responses with an unexpected HTTP status.
+/// Carries the `Retry-After` delay sent by the server, if any.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to fetch {url}: {status}")]
+pub struct HttpStatusError {
+    pub url: String,
+    pub status: reqwest::StatusCode,
+    pub retry_after: Option<Duration>,
+}
+
+impl HttpStatusError {
+    fn from_response(response: &reqwest::Response) -> HttpStatusError {
+        HttpStatusError {
+            url: response.url().to_string(),
+            status: response.status(),
+            retry_after: response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after),
+        }
+    }
+}
+
+/// Parses the value of a `Retry-After` header, which is either a number of seconds or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value.trim()).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
 }
 
-pub async fn download_and_store(url: &str, path: &str) -> anyhow::Result<()> {
-    let response = reqwest::get(url).await?;
+/// Downloads the file at `url` and stores it at `path`, creating missing directories.
+pub async fn download_and_store(client: &HttpClient, url: &str, path: &str) -> anyhow::Result<()> {
+    let response = client.get(url).await?.send().await?;
     
     if response.status().is_success() {
         info!("File {} downloaded successfully", url);
@@ -34,7 +99,8 @@ pub async fn download_and_store(url: &str, path: &str) -> anyhow::Result<()> {
         file.write_all(&content).with_context(|| "Something went wrong storing file")?;
         Ok(())
     } else {
-        Err(anyhow::anyhow!("Failed to download and store file from {}", url))
+        Err(anyhow::Error::new(HttpStatusError::from_response(&response))
+            .context(format!("Failed to download and store file from {}", url)))
     }
 }
 
@@ -45,49 +111,89 @@ fn create_path(path: &str) -> anyhow::Result<()>{
     Ok(())
 }
 
-/// Downloads a given byte range from a URL and unzips the resulting data into a byte Vec.
-/// Does not interpret the output as UTF-8 because the `warc` crate wants plain bytes.
-#[autometrics]
-pub async fn download_and_unzip(
+/// Downloads a given byte range from a URL and returns a stream of the lines of the unzipped data.
+/// Neither the response nor the decompressed data are buffered as a whole,
+/// so memory usage does not depend on the size of the range.
+pub async fn download_and_unzip_lines(
+    client: &HttpClient,
     url: &str,
     offset: usize,
     length: usize,
-) -> Result<Vec<u8>, anyhow::Error> {
-    let client = reqwest::Client::new();
+) -> Result<impl Stream<Item = std::io::Result<String>>, anyhow::Error> {
+    let response = download_range(client, url, offset, length).await?;
+    Ok(unzip_lines(response.bytes_stream().map_err(std::io::Error::other)))
+}
+
+/// Sends a range request for `length` bytes starting at `offset`
+/// and returns the response if the server answered with partial content.
+pub(crate) async fn download_range(
+    client: &HttpClient,
+    url: &str,
+    offset: usize,
+    length: usize,
+) -> Result<reqwest::Response, anyhow::Error> {
     let res = client
         .get(url)
+        .await?
         .header("Range", format!("bytes={}-{}", offset, offset + length - 1))
         .send()
         .await?;
     match res.status() {
-        reqwest::StatusCode::PARTIAL_CONTENT => {
-            let body = res.bytes().await?;
-            tracing::trace!(
-                "Successfully fetched the URL {} from {} to {}",
-                url,
-                offset,
-                offset + length - 1
-            );
-            let mut decoder = flate2::read::GzDecoder::new(&body[..]);
-            let mut buffer = Vec::new();
-            decoder.read_to_end(&mut buffer)?;
-            Ok(buffer)
-        }
-        _ => Err(anyhow::anyhow!(
-            "Failed to fetch index file {}: {}",
-            url,
-            res.status()
-        )),
+        reqwest::StatusCode::PARTIAL_CONTENT => Ok(res),
+        _ => Err(HttpStatusError::from_response(&res).into()),
     }
 }
 
+/// Unzips gzip-compressed data into a byte Vec.
+pub fn unzip(data: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut decoder = flate2::read::GzDecoder::new(data);
+    let mut buffer = Vec::new();
+    decoder.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Unzips a stream of gzip-compressed bytes on the fly and splits the result into lines.
+pub(crate) fn unzip_lines<S>(body: S) -> impl Stream<Item = std::io::Result<String>>
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
+{
+    let mut decoder = GzipDecoder::new(StreamReader::new(body));
+    decoder.multiple_members(true);
+    let lines = BufReader::new(decoder).lines();
+
+    futures_util::stream::unfold(lines, |mut lines| async move {
+        lines.next_line().await.transpose().map(|line| (line, lines))
+    })
+}
+
+/// Parses a stream of cdx index lines into [CdxEntry]s.
+/// Malformed lines are handled according to `on_parse_error`; `source` is only used for error reporting.
+pub fn parse_cdx_lines<S>(
+    lines: S,
+    on_parse_error: ParseErrorPolicy,
+    source: String,
+) -> impl Stream<Item = anyhow::Result<CdxEntry>>
+where
+    S: Stream<Item = std::io::Result<String>>,
+{
+    lines
+        .enumerate()
+        .map(move |(i, line)| {
+            let line = line.with_context(|| format!("Failed to read {}", source))?;
+            on_parse_error.apply(parse_cdx_line(&line, i + 1), &source)
+        })
+        .filter_map(|entry| std::future::ready(entry.transpose()))
+}
+
 /// Represents a line in a cdx index file.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CdxFileContext {
     pub filename: String,
     pub content: String,
     pub target_uri: String,
-    pub tokens: Vec<String>
+    pub tokens: Vec<String>,
+    #[serde(default)]
+    pub metadata: DocumentMetadata,
 }
 
 /// Represents a line in a cdx index file.
@@ -98,39 +204,229 @@ pub struct CdxEntry {
     pub metadata: CdxMetadata,
 }
 
-/// Deserialize an index file fow into a [CdxEntry].
-/// Panics if parsing fails.
-pub fn parse_cdx_line(line: &str) -> CdxEntry {
-    let mut parts = line.splitn(3, ' ');
-    CdxEntry {
-        surt_url: parts.next().unwrap().to_string(),
-        timestamp: parts.next().unwrap().to_string(),
-        metadata: serde_json::from_str(parts.next().unwrap()).unwrap(),
+/// Errors that can occur while parsing a line of a cdx index or cluster.idx file.
+/// Line numbers are 1-based and relative to the parsed content
+/// (i.e. the decompressed chunk for cdx files, the whole file for cluster.idx).
+#[derive(Debug, thiserror::Error)]
+pub enum IndexParseError {
+    #[error("line {line}: missing field `{field}`")]
+    MissingField { line: usize, field: &'static str },
+    #[error("line {line}: invalid JSON metadata: {source}")]
+    InvalidJson {
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("line {line}: invalid integer in field `{field}`: {source}")]
+    InvalidInteger {
+        line: usize,
+        field: &'static str,
+        #[source]
+        source: ParseIntError,
+    },
+}
+
+impl IndexParseError {
+    /// The line at which the error occurred.
+    pub fn line(&self) -> usize {
+        match self {
+            IndexParseError::MissingField { line, .. }
+            | IndexParseError::InvalidJson { line, .. }
+            | IndexParseError::InvalidInteger { line, .. } => *line,
+        }
+    }
+
+    /// A short, stable name of the error kind, e.g. to be used as a metrics label.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IndexParseError::MissingField { .. } => "missing_field",
+            IndexParseError::InvalidJson { .. } => "invalid_json",
+            IndexParseError::InvalidInteger { .. } => "invalid_integer",
+        }
     }
 }
 
+/// What to do with index lines that cannot be parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ParseErrorPolicy {
+    /// Log and count the line, then continue with the next one.
+    #[default]
+    Skip,
+    /// Return the error and stop processing.
+    Fail,
+}
+
+impl ParseErrorPolicy {
+    /// Applies the policy to the result of parsing a line of `source`.
+    /// Returns `Ok(None)` if the line was skipped.
+    pub fn apply<T>(self, result: Result<T, IndexParseError>, source: &str) -> anyhow::Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if self == ParseErrorPolicy::Skip => {
+                tracing::warn!("Skipping malformed line in {}: {}", source, e);
+                increment_counter!("index_lines_skipped", "kind" => e.kind());
+                Ok(None)
+            }
+            Err(e) => Err(e).with_context(|| format!("Failed to parse {}", source)),
+        }
+    }
+}
+
+/// Deserialize an index file row into a [CdxEntry].
+/// `line_number` is only used for error reporting.
+pub fn parse_cdx_line(line: &str, line_number: usize) -> Result<CdxEntry, IndexParseError> {
+    let mut parts = line.splitn(3, ' ');
+    let mut next = |field| {
+        parts
+            .next()
+            .filter(|part| !part.is_empty())
+            .ok_or(IndexParseError::MissingField { line: line_number, field })
+    };
+    let surt_url = next("surt_url")?.to_string();
+    let timestamp = next("timestamp")?.to_string();
+    let metadata = serde_json::from_str(next("metadata")?).map_err(|source| {
+        IndexParseError::InvalidJson {
+            line: line_number,
+            source,
+        }
+    })?;
+
+    Ok(CdxEntry {
+        surt_url,
+        timestamp,
+        metadata,
+    })
+}
+
 /// Represents a line in a cluster.idx file.
-/// We only care about the cdx filename and offset/length pair into that file.
+/// We only care about the first SURT key of the chunk, the cdx filename and offset/length pair
+/// into that file and the incrementing id of the line, which we use to resume processing.
 pub struct ClusterIdxEntry {
-    _surt_url: String,
+    pub surt_url: String,
     _timestamp: String,
     pub cdx_filename: String,
     pub cdx_offset: usize,
     pub cdx_length: usize,
-    _cluster_id: String,
+    pub cluster_id: usize,
 }
 
 /// De-serializes a cluster.idx file line into a [ClusterIdxEntry].
-/// Returns none if there are missing elements in the line.
-/// Panics if parsing fails.
-pub fn parse_cluster_idx(line: &str) -> Option<ClusterIdxEntry> {
+/// `line_number` is only used for error reporting.
+pub fn parse_cluster_idx(line: &str, line_number: usize) -> Result<ClusterIdxEntry, IndexParseError> {
     let mut idx = line.split_whitespace();
-    Some(ClusterIdxEntry {
-        _surt_url: idx.next()?.to_string(),
-        _timestamp: idx.next()?.to_string(),
-        cdx_filename: idx.next()?.to_string(),
-        cdx_offset: idx.next()?.parse().unwrap(),
-        cdx_length: idx.next()?.parse().unwrap(),
-        _cluster_id: idx.next()?.to_string(),
+    let mut next = |field| {
+        idx.next()
+            .ok_or(IndexParseError::MissingField { line: line_number, field })
+    };
+    let parse_int = |field, value: &str| {
+        value
+            .parse()
+            .map_err(|source| IndexParseError::InvalidInteger {
+                line: line_number,
+                field,
+                source,
+            })
+    };
+
+    Ok(ClusterIdxEntry {
+        surt_url: next("surt_url")?.to_string(),
+        _timestamp: next("timestamp")?.to_string(),
+        cdx_filename: next("cdx_filename")?.to_string(),
+        cdx_offset: parse_int("cdx_offset", next("cdx_offset")?)?,
+        cdx_length: parse_int("cdx_length", next("cdx_length")?)?,
+        cluster_id: parse_int("cluster_id", next("cluster_id")?)?,
     })
 }
+
+/// Location of the list of all Common Crawl crawls.
+pub const COLLINFO_URL: &str = "https://index.commoncrawl.org/collinfo.json";
+
+/// Represents a crawl as listed in Common Crawl's `collinfo.json`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct CrawlInfo {
+    /// The crawl name used in all paths, e.g. `CC-MAIN-2024-30`.
+    pub id: String,
+    /// A human-readable name, e.g. `July 2024 Index`.
+    pub name: String,
+    /// Start of the crawl, e.g. `2024-07-12T01:13:38`.
+    pub from: String,
+    /// End of the crawl.
+    pub to: String,
+}
+
+/// Reads the list of crawls from a `collinfo.json` at `location`,
+/// which is either an HTTP(S) URL or a local file path.
+/// Common Crawl lists the most recent crawl first.
+pub async fn load_collinfo(client: &HttpClient, location: &str) -> anyhow::Result<Vec<CrawlInfo>> {
+    let content = if location.starts_with("http://") || location.starts_with("https://") {
+        let response = client.get(location).await?.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to fetch crawl list {}: {}",
+                location,
+                response.status()
+            ));
+        }
+        response.text().await?
+    } else {
+        std::fs::read_to_string(location)
+            .with_context(|| format!("Failed to read crawl list from {}", location))?
+    };
+
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse crawl list {}", location))
+}
+
+/// Selects one or more crawls from a `collinfo.json` listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrawlSelector {
+    /// The most recent crawl, written as `latest`.
+    Latest,
+    /// All crawls that started in the given year, written as e.g. `2024`.
+    Year(String),
+    /// A single crawl by its id, e.g. `CC-MAIN-2024-30`.
+    Id(String),
+}
+
+impl std::str::FromStr for CrawlSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latest" => Ok(CrawlSelector::Latest),
+            year if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) => {
+                Ok(CrawlSelector::Year(year.to_string()))
+            }
+            id if id.starts_with("CC-MAIN-") => Ok(CrawlSelector::Id(id.to_string())),
+            other => Err(anyhow::anyhow!(
+                "Invalid crawl `{}`; expected `latest`, a year like `2024` or a crawl id like `CC-MAIN-2024-30`",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for CrawlSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrawlSelector::Latest => write!(f, "latest"),
+            CrawlSelector::Year(year) => write!(f, "{}", year),
+            CrawlSelector::Id(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl CrawlSelector {
+    /// Returns the crawls in `crawls` matching this selector, in listing order.
+    /// Returns an error if no crawl matches, e.g. because of a typo in a crawl id.
+    pub fn select<'a>(&self, crawls: &'a [CrawlInfo]) -> anyhow::Result<Vec<&'a CrawlInfo>> {
+        let selected: Vec<_> = match self {
+            CrawlSelector::Latest => crawls.iter().max_by(|a, b| a.from.cmp(&b.from)).into_iter().collect(),
+            CrawlSelector::Year(year) => crawls.iter().filter(|c| c.from.starts_with(year.as_str())).collect(),
+            CrawlSelector::Id(id) => crawls.iter().filter(|c| &c.id == id).collect(),
+        };
+        if selected.is_empty() {
+            return Err(anyhow::anyhow!("No crawl matches `{}`", self));
+        }
+        Ok(selected)
+    }
+}
@@ -0,0 +1,121 @@
+//! This module contains helpers to process several crawls at once.
+//! The index entries of every crawl are sorted by SURT, so the entries of all crawls can be merged
+//! into a single sorted stream in which all captures of a URL are adjacent.
+//! [NewestCaptureDeduplicator] then keeps only the most recent of these captures.
+use futures_util::{Stream, StreamExt};
+use metrics::counter;
+
+use crate::commoncrawl::CdxEntry;
+
+/// Which captures are considered duplicates of each other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DedupKey {
+    /// All captures of the same SURT key.
+    #[default]
+    Surt,
+    /// Captures of the same SURT key with the same payload digest.
+    SurtDigest,
+}
+
+/// Merges streams of SURT-sorted [CdxEntry]s into a single stream sorted by SURT key and timestamp.
+/// Every entry is tagged with the index of the stream it came from.
+/// The merged stream ends after the first error of any input stream.
+pub fn merge_sorted<S>(streams: Vec<S>) -> impl Stream<Item = anyhow::Result<(usize, CdxEntry)>>
+where
+    S: Stream<Item = anyhow::Result<CdxEntry>> + Unpin,
+{
+    let heads: Vec<Option<CdxEntry>> = streams.iter().map(|_| None).collect();
+    let exhausted = vec![false; streams.len()];
+
+    futures_util::stream::unfold(
+        (streams, heads, exhausted, false),
+        |(mut streams, mut heads, mut exhausted, failed)| async move {
+            if failed {
+                return None;
+            }
+            for i in 0..streams.len() {
+                if heads[i].is_some() || exhausted[i] {
+                    continue;
+                }
+                match streams[i].next().await {
+                    Some(Ok(entry)) => heads[i] = Some(entry),
+                    Some(Err(e)) => return Some((Err(e), (streams, heads, exhausted, true))),
+                    None => exhausted[i] = true,
+                }
+            }
+
+            // `min_by_key` returns the first minimum, so ties are resolved in stream order
+            let next = heads
+                .iter()
+                .enumerate()
+                .filter_map(|(i, head)| head.as_ref().map(|e| (i, (&e.surt_url, &e.timestamp))))
+                .min_by_key(|(_, key)| *key)
+                .map(|(i, _)| i)?;
+            let entry = heads[next].take()?;
+            Some((Ok((next, entry)), (streams, heads, exhausted, false)))
+        },
+    )
+}
+
+/// Keeps only the most recent capture per [DedupKey] of a SURT-sorted entry stream
+/// (e.g. the output of [merge_sorted]) and counts the dropped captures per source.
+pub struct NewestCaptureDeduplicator {
+    key: DedupKey,
+    sources: Vec<String>,
+    group: Vec<(usize, CdxEntry)>,
+    duplicates: Vec<usize>,
+}
+
+impl NewestCaptureDeduplicator {
+    /// Creates a deduplicator for entries from `sources`, e.g. the names of the merged crawls.
+    pub fn new(key: DedupKey, sources: Vec<String>) -> NewestCaptureDeduplicator {
+        let duplicates = vec![0; sources.len()];
+        NewestCaptureDeduplicator {
+            key,
+            sources,
+            group: Vec::new(),
+            duplicates,
+        }
+    }
+
+    /// Adds the next entry of the sorted stream, coming from source `source`.
+    /// Returns the surviving entries of the previous SURT key once a new key starts.
+    pub fn push(&mut self, source: usize, entry: CdxEntry) -> Vec<CdxEntry> {
+        let finished = match self.group.first() {
+            Some((_, first)) if first.surt_url != entry.surt_url => self.finish(),
+            _ => Vec::new(),
+        };
+        self.group.push((source, entry));
+        finished
+    }
+
+    /// Returns the surviving entries of the current SURT key.
+    /// Has to be called once the input stream ended.
+    pub fn finish(&mut self) -> Vec<CdxEntry> {
+        let mut survivors: Vec<(usize, CdxEntry)> = Vec::new();
+        for (source, entry) in self.group.drain(..) {
+            let existing = survivors.iter_mut().find(|(_, survivor)| match self.key {
+                DedupKey::Surt => true,
+                DedupKey::SurtDigest => survivor.metadata.digest == entry.metadata.digest,
+            });
+            let dropped = match existing {
+                Some(existing) if existing.1.timestamp < entry.timestamp => {
+                    std::mem::replace(existing, (source, entry)).0
+                }
+                Some(_) => source,
+                None => {
+                    survivors.push((source, entry));
+                    continue;
+                }
+            };
+            self.duplicates[dropped] += 1;
+            counter!("batcher_duplicates_dropped", 1, "crawl" => self.sources[dropped].clone());
+        }
+        survivors.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// The number of dropped duplicates per source, in the order of the sources.
+    pub fn duplicates(&self) -> &[usize] {
+        &self.duplicates
+    }
+}
@@ -1,10 +1,79 @@
-from typing import Optional
+import json
+import struct
+import sys
+from typing import Any, BinaryIO, Dict, List, Optional, Union
 from trafilatura import extract
+from trafilatura.metadata import extract_metadata
+from trafilatura.utils import load_html
 
-def extract_text(content: str) -> Optional[str]:
-    text = extract(content, include_comments=False,
-                   include_tables=False, deduplicate=True)
+# status bytes of the responses when running as an extraction process, see `serve`
+STATUS_NONE = 0
+STATUS_DOCUMENT = 1
+STATUS_ERROR = 2
+
+# see `DocumentMetadata` on the Rust side
+METADATA_FIELDS = ("title", "author", "date", "sitename", "description")
+
+
+def extract_document(content: str, options_json: str) -> Optional[str]:
+    """Extracts the text of `content` in the requested output format and its metadata and returns them as JSON object.
+    The HTML is only parsed once; like trafilatura does itself, the metadata are read before the tree is cleaned."""
+    # the keyword arguments of `trafilatura.extract`, see `ExtractionOptions` on the Rust side
+    options = json.loads(options_json)
+    if options.get("output_format") == "markdown":
+        # without the formatting, the markdown output is plain text
+        options["include_formatting"] = True
+    tree = load_html(content)
+    if tree is None:
+        return None
+    # the attributes of trafilatura's document, unlike its JSON output, which e.g. renames `sitename`
+    metadata = extract_metadata(tree)
+    text = extract(tree, **options)
     # also return None if utf-8 decoding failed
     if text is None or isinstance(text, bytes):
         return None
-    return text
\ No newline at end of file
+    document: Dict[str, Any] = {"text": text}
+    for field in METADATA_FIELDS:
+        document[field] = getattr(metadata, field, None) or None
+    document["categories"] = split_categories(getattr(metadata, "categories", None))
+    return json.dumps(document)
+
+
+def split_categories(categories: Union[str, List[str], None]) -> List[str]:
+    """Categories are a list in trafilatura's document, but may also be joined with `;`."""
+    if not categories:
+        return []
+    if isinstance(categories, str):
+        categories = categories.split(";")
+    return [category.strip() for category in categories if category.strip()]
+
+
+def serve(requests: BinaryIO, responses: BinaryIO, options_json: str) -> None:
+    """Extracts documents with the given options until `requests` is closed.
+    A request is a big-endian u32 length followed by the UTF-8 encoded HTML.
+    A response is a status byte, a big-endian u32 length and the UTF-8 encoded document or error message."""
+    while True:
+        header = requests.read(4)
+        if len(header) < 4:
+            return
+        (length,) = struct.unpack(">I", header)
+        content = requests.read(length).decode("utf-8", errors="replace")
+        try:
+            document = extract_document(content, options_json)
+            if document is None:
+                status, payload = STATUS_NONE, b""
+            else:
+                status, payload = STATUS_DOCUMENT, document.encode("utf-8")
+        except Exception as e:
+            status, payload = STATUS_ERROR, repr(e).encode("utf-8")
+        responses.write(struct.pack(">BI", status, len(payload)))
+        responses.write(payload)
+        responses.flush()
+
+
+if __name__ == "__main__":
+    responses = sys.stdout.buffer
+    # anything printed by trafilatura must not end up in the protocol
+    sys.stdout = sys.stderr
+    # the options are the only argument, as JSON
+    serve(sys.stdin.buffer, responses, sys.argv[1])
@@ -0,0 +1,67 @@
+//! This module contains the pool that runs text extraction off the async runtime.
+//! Extraction is CPU-bound and, for trafilatura, holds the Python GIL. Running it directly in an async task
+//! blocks a Tokio worker thread for every document, stalling the downloads that share the thread.
+//! The pool runs extractions on blocking threads, at most `size` at once, and queues the remaining ones.
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Context;
+use metrics::{decrement_gauge, histogram, increment_gauge};
+use tokio::sync::Semaphore;
+
+/// A bounded pool of threads for blocking work. Clones share the same pool.
+#[derive(Debug, Clone)]
+pub struct ExtractionPool {
+    permits: Arc<Semaphore>,
+}
+
+impl ExtractionPool {
+    /// Creates a pool running up to `size` jobs at once.
+    pub fn new(size: usize) -> Self {
+        ExtractionPool {
+            permits: Arc::new(Semaphore::new(size.max(1))),
+        }
+    }
+
+    /// Runs `job` on a blocking thread once the pool has capacity and returns its result.
+    pub async fn run<T, F>(&self, job: F) -> anyhow::Result<T>
+    where
+        F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let queued = Instant::now();
+        let permit = {
+            let _waiting = GaugeGuard::new("extraction_pool_queue_depth");
+            self.permits.clone().acquire_owned().await?
+        };
+        histogram!("extraction_pool_wait_seconds", queued.elapsed().as_secs_f64());
+
+        let _busy = GaugeGuard::new("extraction_pool_busy");
+        let started = Instant::now();
+        let result = tokio::task::spawn_blocking(move || {
+            let result = job();
+            drop(permit);
+            result
+        })
+        .await
+        .context("Extraction job panicked")?;
+        histogram!("extraction_pool_run_seconds", started.elapsed().as_secs_f64());
+        result
+    }
+}
+
+/// Increments a gauge while alive, so that it is also decremented if the future is dropped.
+struct GaugeGuard(&'static str);
+
+impl GaugeGuard {
+    fn new(name: &'static str) -> Self {
+        increment_gauge!(name, 1.0);
+        GaugeGuard(name)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        decrement_gauge!(self.0, 1.0);
+    }
+}
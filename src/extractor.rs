@@ -0,0 +1,194 @@
+//! This module contains the [Extractor] trait that turns the HTML of a document into its text,
+//! and the selection of the extraction backend at worker startup.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::extraction_pool::ExtractionPool;
+use crate::python_processes::{ProcessCommand, PythonProcessPool};
+use crate::readability::ReadabilityExtractor;
+
+/// Extracts the main text and the metadata of an HTML document.
+#[async_trait]
+pub trait Extractor: Send + Sync {
+    /// Returns the extracted document, or `Ok(None)` if the document does not contain any text.
+    async fn extract(&self, html: String) -> anyhow::Result<Option<ExtractedDocument>>;
+}
+
+/// The result of an extraction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractedDocument {
+    pub text: String,
+    #[serde(flatten)]
+    pub metadata: DocumentMetadata,
+}
+
+/// Metadata of a page, as far as the extraction backend could find it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// The publication date as given by the backend, usually in ISO 8601 format.
+    pub date: Option<String>,
+    pub sitename: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+/// The format of the extracted text.
+#[derive(clap::ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Plain text.
+    #[default]
+    Txt,
+    /// Markdown, keeping headings, lists and (with `--include-tables`) tables.
+    Markdown,
+    /// trafilatura's XML format. Not supported by the `readability` backend.
+    Xml,
+}
+
+/// Whether extraction prefers leaving out boilerplate or keeping all of the content.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExtractionFocus {
+    #[default]
+    Balanced,
+    /// Less text, but less noise.
+    Precision,
+    /// More text, but more noise.
+    Recall,
+}
+
+impl Serialize for ExtractionFocus {
+    /// Serializes to the `favor_precision` and `favor_recall` flags of trafilatura.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("favor_precision", &(*self == ExtractionFocus::Precision))?;
+        map.serialize_entry("favor_recall", &(*self == ExtractionFocus::Recall))?;
+        map.end()
+    }
+}
+
+/// Options of a text extraction, named after the parameters of `trafilatura.extract`.
+/// Serialized to JSON, they are passed to `extract_text.py` as keyword arguments.
+#[derive(clap::Args, Serialize, Debug, Clone, PartialEq)]
+pub struct ExtractionOptions {
+    /// Keep the comments section of a page.
+    #[arg(long("include-comments"))]
+    pub include_comments: bool,
+
+    /// Keep tables.
+    #[arg(long("include-tables"))]
+    pub include_tables: bool,
+
+    /// Keep duplicated paragraphs instead of dropping them.
+    #[arg(long("no-deduplicate"), action = clap::ArgAction::SetFalse)]
+    pub deduplicate: bool,
+
+    /// Keep links, with their targets.
+    #[arg(long("include-links"))]
+    pub include_links: bool,
+
+    /// Keep text formatting like bold and italic.
+    #[arg(long("include-formatting"))]
+    pub include_formatting: bool,
+
+    /// The format of the extracted text.
+    #[arg(long("output-format"), value_enum, default_value_t = OutputFormat::Txt)]
+    pub output_format: OutputFormat,
+
+    /// Whether to favor precision or recall.
+    #[arg(long("extraction-focus"), value_enum, default_value_t = ExtractionFocus::Balanced)]
+    #[serde(flatten)]
+    pub focus: ExtractionFocus,
+}
+
+impl Default for ExtractionOptions {
+    /// The options used before they became configurable: no comments and tables, deduplicated plain text.
+    fn default() -> Self {
+        ExtractionOptions {
+            include_comments: false,
+            include_tables: false,
+            deduplicate: true,
+            include_links: false,
+            include_formatting: false,
+            output_format: OutputFormat::Txt,
+            focus: ExtractionFocus::Balanced,
+        }
+    }
+}
+
+/// The available extraction backends.
+/// `in-process` and `processes` are still accepted for the two trafilatura backends.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ExtractorBackend {
+    /// trafilatura in the interpreter embedded with PyO3. Only one document is extracted at a time.
+    /// Requires the `python` feature.
+    #[value(alias = "in-process")]
+    Trafilatura,
+    /// trafilatura in a pool of Python child processes, extracting documents in parallel.
+    #[value(alias = "processes")]
+    TrafilaturaProcesses,
+    /// A pure-Rust port of Mozilla's Readability, not requiring Python at all.
+    Readability,
+}
+
+/// Command line arguments to select and configure the [Extractor] of a worker.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ExtractorArgs {
+    /// The extraction backend.
+    #[arg(long("extraction-backend"), value_enum, default_value_t = ExtractorBackend::Trafilatura)]
+    pub backend: ExtractorBackend,
+
+    /// The number of threads running text extraction with the `trafilatura` and `readability` backends,
+    /// separate from the threads doing network I/O.
+    #[arg(long("extraction-threads"), default_value_t = 2,
+    value_parser = clap::value_parser!(u64).range(1..=64))]
+    pub threads: u64,
+
+    /// The number of Python processes of the `trafilatura-processes` backend.
+    #[arg(long("python-processes"), default_value_t = 4,
+    value_parser = clap::value_parser!(u64).range(1..=64))]
+    pub python_processes: u64,
+
+    /// The Python interpreter of the `trafilatura-processes` backend; it needs trafilatura installed.
+    #[arg(long("python"), default_value = "python3")]
+    pub python: String,
+
+    /// The time the `trafilatura-processes` backend may take for a single document, in seconds.
+    #[arg(long("extraction-timeout-secs"), default_value_t = 60)]
+    pub timeout_secs: u64,
+
+    #[command(flatten)]
+    pub options: ExtractionOptions,
+}
+
+impl ExtractorArgs {
+    /// Creates the selected backend.
+    pub fn build(&self) -> anyhow::Result<Arc<dyn Extractor>> {
+        let pool = ExtractionPool::new(self.threads as usize);
+        Ok(match self.backend {
+            ExtractorBackend::Trafilatura => trafilatura_in_process(pool, &self.options)?,
+            ExtractorBackend::TrafilaturaProcesses => Arc::new(PythonProcessPool::new(
+                ProcessCommand::trafilatura(&self.python, &self.options)?,
+                self.python_processes as usize,
+                Duration::from_secs(self.timeout_secs),
+            )?),
+            ExtractorBackend::Readability => Arc::new(ReadabilityExtractor::new(pool, &self.options)?),
+        })
+    }
+}
+
+#[cfg(feature = "python")]
+fn trafilatura_in_process(pool: ExtractionPool, options: &ExtractionOptions) -> anyhow::Result<Arc<dyn Extractor>> {
+    Ok(Arc::new(crate::trafilatura::InProcessTrafilatura::new(pool, options)))
+}
+
+#[cfg(not(feature = "python"))]
+fn trafilatura_in_process(_pool: ExtractionPool, _options: &ExtractionOptions) -> anyhow::Result<Arc<dyn Extractor>> {
+    anyhow::bail!("The `trafilatura` backend needs the `python` feature; use `trafilatura-processes` or `readability`")
+}
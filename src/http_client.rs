@@ -0,0 +1,85 @@
+//! This module contains the configuration of the HTTP client that a binary uses for all its downloads.
+//! The client is constructed once and shared, so that connections to Common Crawl are pooled and reused.
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crate::rate_limit::RateLimiter;
+
+/// The default User-Agent, identifying the pipeline towards Common Crawl.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "basic-common-crawl-pipeline/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/lucaghersi/basic-common-crawl-pipeline)"
+);
+
+/// Command line arguments to configure the shared HTTP client.
+#[derive(clap::Args, Debug, Clone)]
+pub struct HttpClientArgs {
+    /// The User-Agent header sent with every request.
+    #[arg(long("http-user-agent"), default_value = DEFAULT_USER_AGENT)]
+    pub user_agent: String,
+
+    /// Timeout for establishing a connection in seconds.
+    #[arg(long("http-connect-timeout-secs"), default_value_t = 10)]
+    pub connect_timeout_secs: u64,
+
+    /// Timeout for a single read from a connection in seconds.
+    #[arg(long("http-read-timeout-secs"), default_value_t = 60)]
+    pub read_timeout_secs: u64,
+
+    /// Maximum number of idle connections kept open per host.
+    #[arg(long("http-pool-max-idle-per-host"), default_value_t = 32)]
+    pub pool_max_idle_per_host: usize,
+
+    /// Proxy used for all requests, e.g. `http://proxy:3128`.
+    #[arg(long("http-proxy"))]
+    pub proxy: Option<String>,
+
+    /// Talk HTTP/2 right away instead of negotiating the protocol. Only use with servers supporting it.
+    #[arg(long("http2-prior-knowledge"))]
+    pub http2_prior_knowledge: bool,
+}
+
+impl HttpClientArgs {
+    /// Builds the HTTP client from the configuration.
+    pub fn build(&self) -> anyhow::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .read_timeout(Duration::from_secs(self.read_timeout_secs))
+            .pool_max_idle_per_host(self.pool_max_idle_per_host);
+        if let Some(proxy) = &self.proxy {
+            builder = builder
+                .proxy(reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy {}", proxy))?);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        builder.build().context("Failed to build HTTP client")
+    }
+}
+
+/// The HTTP client used for all downloads from Common Crawl.
+/// Every request waits for the rate limiter before it is sent. Clones share the pool and the limiter.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl HttpClient {
+    pub fn new(client: reqwest::Client, rate_limiter: RateLimiter) -> Self {
+        HttpClient {
+            client,
+            rate_limiter: Arc::new(rate_limiter),
+        }
+    }
+
+    /// Waits until the rate limiter allows another request and returns a GET request for `url`.
+    pub async fn get(&self, url: &str) -> anyhow::Result<reqwest::RequestBuilder> {
+        self.rate_limiter.acquire().await?;
+        Ok(self.client.get(url))
+    }
+}
@@ -0,0 +1,179 @@
+//! This module contains the parsing of the HTTP responses stored in WARC `response` records.
+//! The body is decoded as the server sent it (chunked transfer encoding, gzip, deflate or brotli content encoding)
+//! and converted to UTF-8 using the charset of the `Content-Type` header, a `<meta>` tag or the cdx index.
+use std::io::Read;
+
+use encoding_rs::Encoding;
+use thiserror::Error;
+
+/// Upper bound of the number of headers of a response.
+const MAX_HEADERS: usize = 256;
+/// How far into the body to look for a `<meta charset>` tag, like browsers do.
+const META_CHARSET_SCAN_LENGTH: usize = 1024;
+
+/// Errors that can occur while parsing an HTTP response.
+#[derive(Debug, Error)]
+pub enum HttpParseError {
+    #[error("incomplete HTTP response header")]
+    IncompleteHeader,
+    #[error("invalid HTTP response header: {0}")]
+    InvalidHeader(#[from] httparse::Error),
+    #[error("invalid chunked transfer encoding")]
+    InvalidChunkedEncoding,
+    #[error("failed to decode {encoding} content encoding: {source}")]
+    InvalidContentEncoding {
+        encoding: String,
+        source: std::io::Error,
+    },
+    #[error("unsupported content encoding {0}")]
+    UnsupportedContentEncoding(String),
+}
+
+/// An HTTP response with its (still encoded) body.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Parses the status line, the headers and the body of an HTTP response.
+    pub fn parse(data: &[u8]) -> Result<HttpResponse, HttpParseError> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut response = httparse::Response::new(&mut headers);
+        let body_start = match response.parse(data)? {
+            httparse::Status::Complete(length) => length,
+            httparse::Status::Partial => return Err(HttpParseError::IncompleteHeader),
+        };
+        Ok(HttpResponse {
+            status: response.code.unwrap_or_default(),
+            headers: response
+                .headers
+                .iter()
+                .map(|header| {
+                    (
+                        header.name.to_string(),
+                        String::from_utf8_lossy(header.value).trim().to_string(),
+                    )
+                })
+                .collect(),
+            body: data[body_start..].to_vec(),
+        })
+    }
+
+    /// Returns the value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the media type of the `Content-Type` header, in lower case and without parameters.
+    pub fn mime_type(&self) -> Option<String> {
+        let content_type = self.header("Content-Type")?;
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        (!mime.is_empty()).then_some(mime)
+    }
+
+    /// Returns the `charset` parameter of the `Content-Type` header.
+    pub fn charset(&self) -> Option<&str> {
+        self.header("Content-Type")?
+            .split(';')
+            .skip(1)
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+    }
+
+    /// Returns the body with the transfer and content encodings removed.
+    pub fn decoded_body(&self) -> Result<Vec<u8>, HttpParseError> {
+        let mut body = if self.has_token("Transfer-Encoding", "chunked") {
+            dechunk(&self.body)?
+        } else {
+            self.body.clone()
+        };
+        if let Some(encodings) = self.header("Content-Encoding") {
+            // encodings are listed in the order they were applied
+            for encoding in encodings.rsplit(',').map(|e| e.trim().to_ascii_lowercase()) {
+                body = decode_content(&body, &encoding)?;
+            }
+        }
+        Ok(body)
+    }
+
+    /// Returns the decoded body as UTF-8. The charset is taken from the `Content-Type` header,
+    /// a `<meta>` tag in the body or `fallback_charset` (e.g. the `charset` of the cdx index), in that order.
+    /// Falls back to UTF-8; invalid sequences are replaced.
+    pub fn text(&self, fallback_charset: Option<&str>) -> Result<String, HttpParseError> {
+        let body = self.decoded_body()?;
+        let encoding = self
+            .charset()
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+            .or_else(|| meta_charset(&body))
+            .or_else(|| fallback_charset.and_then(|label| Encoding::for_label(label.as_bytes())))
+            .unwrap_or(encoding_rs::UTF_8);
+        // a byte order mark takes precedence over all of the above
+        let (text, _, _) = encoding.decode(&body);
+        Ok(text.into_owned())
+    }
+
+    fn has_token(&self, header: &str, token: &str) -> bool {
+        self.header(header)
+            .is_some_and(|value| value.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    }
+}
+
+/// Removes chunked transfer encoding. Trailers after the last chunk are ignored.
+fn dechunk(data: &[u8]) -> Result<Vec<u8>, HttpParseError> {
+    let mut body = Vec::with_capacity(data.len());
+    let mut rest = data;
+    loop {
+        let line_end = rest
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or(HttpParseError::InvalidChunkedEncoding)?;
+        let size_line = std::str::from_utf8(&rest[..line_end]).map_err(|_| HttpParseError::InvalidChunkedEncoding)?;
+        // chunk extensions follow a `;`
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| HttpParseError::InvalidChunkedEncoding)?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = rest.get(..size).ok_or(HttpParseError::InvalidChunkedEncoding)?;
+        body.extend_from_slice(chunk);
+        rest = rest[size..].strip_prefix(b"\r\n").ok_or(HttpParseError::InvalidChunkedEncoding)?;
+    }
+}
+
+fn decode_content(data: &[u8], encoding: &str) -> Result<Vec<u8>, HttpParseError> {
+    let mut decoded = Vec::new();
+    let result = match encoding {
+        "identity" | "" => return Ok(data.to_vec()),
+        "gzip" | "x-gzip" => flate2::read::MultiGzDecoder::new(data).read_to_end(&mut decoded),
+        "deflate" => flate2::read::ZlibDecoder::new(data).read_to_end(&mut decoded),
+        "br" => brotli::Decompressor::new(data, 4096).read_to_end(&mut decoded),
+        other => return Err(HttpParseError::UnsupportedContentEncoding(other.to_string())),
+    };
+    result.map_err(|source| HttpParseError::InvalidContentEncoding {
+        encoding: encoding.to_string(),
+        source,
+    })?;
+    Ok(decoded)
+}
+
+/// Finds the charset of `<meta charset="...">` or `<meta http-equiv="Content-Type" content="...; charset=...">`
+/// at the beginning of an HTML document.
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = &body[..body.len().min(META_CHARSET_SCAN_LENGTH)];
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    head.match_indices("charset=").find_map(|(position, _)| {
+        let value = head[position + "charset=".len()..].trim_start_matches(['"', '\'', ' ']);
+        let end = value
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':' || c == '.'))
+            .unwrap_or(value.len());
+        Encoding::for_label(&value.as_bytes()[..end])
+    })
+}
@@ -1,6 +1,23 @@
 //! This crate consists of two binaries, called [batcher](../batcher/index.html) and [worker](../worker/index.html)
+pub mod batch_processing;
+pub mod cdx_filter;
+pub mod checkpoint;
 pub mod commoncrawl;
+pub mod crawl_merge;
+pub mod extraction_pool;
+pub mod extractor;
+pub mod http_client;
+pub mod http_response;
+pub mod python_processes;
 pub mod rabbitmq;
+pub mod rate_limit;
+pub mod readability;
+pub mod retry;
+pub mod source;
+pub mod surt;
 pub mod tracing_and_metrics;
+#[cfg(feature = "python")]
 pub mod trafilatura;
 pub mod utility;
+pub mod warc_ranges;
+pub mod warc_record;
@@ -0,0 +1,170 @@
+//! This module contains a pool of long-lived Python child processes running `extract_text.py`.
+//! The embedded interpreter of [crate::trafilatura] holds the GIL for every document, so a worker can only use
+//! a single core for extraction. Every child process has its own interpreter, so `size` processes extract in parallel.
+//!
+//! Requests and responses are framed on stdin/stdout of the child (see `serve` in `extract_text.py`).
+//! A child that crashes, answers with garbage or exceeds the timeout is killed and replaced by a new one.
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use metrics::{histogram, increment_counter};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Semaphore;
+
+use crate::extractor::{ExtractedDocument, ExtractionOptions, Extractor};
+
+/// The extraction script, passed to the interpreter with `-c` so that it does not need to be deployed separately.
+pub const EXTRACT_TEXT_SCRIPT: &str = include_str!("extract_text.py");
+
+const STATUS_NONE: u8 = 0;
+const STATUS_DOCUMENT: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+/// Upper bound of a response, to not allocate arbitrary amounts of memory for a corrupt length.
+const MAX_RESPONSE_LENGTH: usize = 256 * 1024 * 1024;
+
+/// How to start an extraction process.
+#[derive(Debug, Clone)]
+pub struct ProcessCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl ProcessCommand {
+    /// Runs `extract_text.py` with the given Python interpreter and extraction options.
+    pub fn trafilatura(python: &str, options: &ExtractionOptions) -> anyhow::Result<Self> {
+        Ok(ProcessCommand {
+            program: python.to_string(),
+            args: vec![
+                "-c".to_string(),
+                EXTRACT_TEXT_SCRIPT.to_string(),
+                serde_json::to_string(options)?,
+            ],
+        })
+    }
+}
+
+/// A pool of up to `size` extraction processes. Clones share the same processes.
+#[derive(Debug, Clone)]
+pub struct PythonProcessPool {
+    command: ProcessCommand,
+    timeout: Duration,
+    permits: Arc<Semaphore>,
+    idle: Arc<Mutex<Vec<PythonProcess>>>,
+}
+
+impl PythonProcessPool {
+    /// Starts `size` processes. Each document has to be extracted within `timeout`.
+    pub fn new(command: ProcessCommand, size: usize, timeout: Duration) -> anyhow::Result<Self> {
+        let size = size.max(1);
+        let processes = (0..size)
+            .map(|_| PythonProcess::spawn(&command))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(PythonProcessPool {
+            command,
+            timeout,
+            permits: Arc::new(Semaphore::new(size)),
+            idle: Arc::new(Mutex::new(processes)),
+        })
+    }
+
+    /// Extracts the text and metadata of `html` in one of the processes.
+    /// Might return `Ok(None)` if text extraction was not successful.
+    pub async fn extract(&self, html: &str) -> anyhow::Result<Option<ExtractedDocument>> {
+        let _permit = self.permits.acquire().await?;
+        let idle = self.idle.lock().unwrap().pop();
+        let mut process = match idle {
+            Some(process) => process,
+            None => {
+                // the previous process of this slot was discarded
+                increment_counter!("python_process_restarts");
+                PythonProcess::spawn(&self.command)?
+            }
+        };
+
+        let started = Instant::now();
+        match tokio::time::timeout(self.timeout, process.extract(html)).await {
+            Ok(Ok(response)) => {
+                histogram!("python_process_extraction_seconds", started.elapsed().as_secs_f64());
+                self.idle.lock().unwrap().push(process);
+                response
+            }
+            Ok(Err(e)) => {
+                // the process is in an unknown state; it is killed when dropped
+                Err(e.context("Extraction process failed"))
+            }
+            Err(_) => {
+                increment_counter!("python_process_timeouts");
+                Err(anyhow::anyhow!("Extraction did not finish within {:?}", self.timeout))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Extractor for PythonProcessPool {
+    async fn extract(&self, html: String) -> anyhow::Result<Option<ExtractedDocument>> {
+        PythonProcessPool::extract(self, &html).await
+    }
+}
+
+/// A single extraction process.
+#[derive(Debug)]
+struct PythonProcess {
+    // kept to kill the process when it is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl PythonProcess {
+    fn spawn(command: &ProcessCommand) -> anyhow::Result<Self> {
+        let mut child = Command::new(&command.program)
+            .args(&command.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start extraction process {}", command.program))?;
+        let stdin = child.stdin.take().context("Extraction process without stdin")?;
+        let stdout = child.stdout.take().context("Extraction process without stdout")?;
+        Ok(PythonProcess {
+            _child: child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Sends a request and reads the response. The outer error means the process is unusable,
+    /// the inner one that the extraction failed within Python.
+    async fn extract(&mut self, html: &str) -> anyhow::Result<anyhow::Result<Option<ExtractedDocument>>> {
+        let length = u32::try_from(html.len()).context("Document too large for extraction")?;
+        self.stdin.write_all(&length.to_be_bytes()).await?;
+        self.stdin.write_all(html.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        let status = self.stdout.read_u8().await?;
+        let length = self.stdout.read_u32().await? as usize;
+        anyhow::ensure!(
+            length <= MAX_RESPONSE_LENGTH,
+            "Extraction process sent a response of {} bytes",
+            length
+        );
+        let mut payload = vec![0; length];
+        self.stdout.read_exact(&mut payload).await?;
+        let payload = String::from_utf8(payload).context("Extraction process sent invalid UTF-8")?;
+
+        match status {
+            STATUS_NONE => Ok(Ok(None)),
+            STATUS_DOCUMENT => Ok(Ok(Some(
+                serde_json::from_str(&payload).context("Extraction process sent an invalid document")?,
+            ))),
+            STATUS_ERROR => Ok(Err(anyhow::anyhow!("Extraction failed: {}", payload))),
+            other => Err(anyhow::anyhow!("Extraction process sent unknown status {}", other)),
+        }
+    }
+}
@@ -1,19 +1,149 @@
 //! This module contains helper functions to interact with the RabbitMQ service.
+//!
+//! Every work queue `<queue>` comes with two more queues:
+//! - `<queue>.retry` holds messages that failed transiently. They expire after a delay (message TTL)
+//!   and are then dead-lettered back into `<queue>`. The attempt is counted in the [ATTEMPT_HEADER] header.
+//! - `<queue>.dead` receives messages that are rejected by a consumer or exceeded the maximum number of attempts,
+//!   via the [DEAD_LETTER_EXCHANGE]. They stay there for inspection.
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 use lapin::{
-    options::{BasicConsumeOptions, BasicPublishOptions, BasicQosOptions, QueueDeclareOptions},
-    types::FieldTable,
-    BasicProperties, Channel, Connection, ConnectionProperties, Queue,
+    message::Delivery,
+    options::{
+        BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions, BasicQosOptions,
+        ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
+    },
+    types::{AMQPValue, FieldTable, ShortString},
+    BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind, Queue,
 };
+use metrics::increment_counter;
 use serde::Serialize;
 
 pub const BATCH_SIZE: usize = 1000;
 pub const CC_QUEUE_NAME_BATCHES: &str = "batches";
 pub const CC_QUEUE_NAME_STORE: &str = "stores";
+/// The exchange that routes rejected messages of a work queue to its dead-letter queue.
+pub const DEAD_LETTER_EXCHANGE: &str = "dead-letters";
+/// Header holding the (1-based) attempt of a message. Messages without it are on their first attempt.
+pub const ATTEMPT_HEADER: &str = "x-attempt";
 const RABBIT_MQ_TIMEOUT: Duration = Duration::from_secs(20);
 
+/// Command line arguments to configure a [RedeliveryPolicy], shared by all binaries that consume messages.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RedeliveryArgs {
+    /// How often a message is processed before it is moved to the dead-letter queue.
+    /// The explicit id keeps it apart from `--retry-max-attempts` when both are flattened into the same binary.
+    #[arg(id = "redelivery_max_attempts", long("redelivery-max-attempts"), default_value_t = 5,
+    value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_attempts: u32,
+
+    /// How long a message that failed waits in the retry queue before it is delivered again, in seconds.
+    #[arg(long("redelivery-delay-secs"), default_value_t = 30)]
+    pub delay_secs: u64,
+}
+
+impl RedeliveryArgs {
+    pub fn policy(&self) -> RedeliveryPolicy {
+        RedeliveryPolicy {
+            max_attempts: self.max_attempts,
+            delay: Duration::from_secs(self.delay_secs),
+        }
+    }
+}
+
+/// How often and after which delay a message that failed is delivered again.
+#[derive(Debug, Clone)]
+pub struct RedeliveryPolicy {
+    pub max_attempts: u32,
+    pub delay: Duration,
+}
+
+impl Default for RedeliveryPolicy {
+    fn default() -> Self {
+        RedeliveryPolicy {
+            max_attempts: 5,
+            delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What happened to a message that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redelivery {
+    /// The message was put into the retry queue for the given attempt.
+    Retried { attempt: u32 },
+    /// The message was moved to the dead-letter queue.
+    DeadLettered,
+}
+
+/// The single terminal action taken for a delivery, see [settle].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// The message was processed and is removed from the queue.
+    Ack,
+    /// The message failed transiently and is delivered again after a delay, see [retry_or_dead_letter].
+    Requeue,
+    /// The message can never be processed and is moved to the dead-letter queue.
+    DeadLetter,
+}
+
+/// Name of the queue that delays the messages of `queue_name` before they are delivered again.
+pub fn retry_queue_name(queue_name: &str) -> String {
+    format!("{queue_name}.retry")
+}
+
+/// Name of the queue that collects the rejected messages of `queue_name`.
+pub fn dead_letter_queue_name(queue_name: &str) -> String {
+    format!("{queue_name}.dead")
+}
+
+/// Arguments of a work queue: rejected messages go to its dead-letter queue.
+pub fn work_queue_arguments(queue_name: &str) -> FieldTable {
+    let mut arguments = FieldTable::default();
+    arguments.insert("x-dead-letter-exchange".into(), AMQPValue::LongString(DEAD_LETTER_EXCHANGE.into()));
+    arguments.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(queue_name.into()));
+    arguments
+}
+
+/// Arguments of a retry queue: expired messages go back into the work queue (through the default exchange).
+pub fn retry_queue_arguments(queue_name: &str) -> FieldTable {
+    let mut arguments = FieldTable::default();
+    arguments.insert("x-dead-letter-exchange".into(), AMQPValue::LongString("".into()));
+    arguments.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(queue_name.into()));
+    arguments
+}
+
+/// Returns the attempt of a message as counted in its [ATTEMPT_HEADER] header.
+pub fn delivery_attempt(properties: &BasicProperties) -> u32 {
+    let value = properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(ATTEMPT_HEADER).cloned());
+    let attempt = match value {
+        Some(AMQPValue::ShortShortUInt(n)) => n as i64,
+        Some(AMQPValue::ShortUInt(n)) => n as i64,
+        Some(AMQPValue::LongUInt(n)) => n as i64,
+        Some(AMQPValue::ShortShortInt(n)) => n as i64,
+        Some(AMQPValue::ShortInt(n)) => n as i64,
+        Some(AMQPValue::LongInt(n)) => n as i64,
+        Some(AMQPValue::LongLongInt(n)) => n,
+        _ => 1,
+    };
+    attempt.clamp(1, u32::MAX as i64) as u32
+}
+
+/// Properties of a message that is retried: the headers of the original message with the new attempt,
+/// and an expiration after which the message leaves the retry queue.
+pub fn retry_properties(properties: &BasicProperties, attempt: u32, delay: Duration) -> BasicProperties {
+    let mut headers = properties.headers().clone().unwrap_or_default();
+    headers.insert(ATTEMPT_HEADER.into(), AMQPValue::LongUInt(attempt));
+    properties
+        .clone()
+        .with_headers(headers)
+        .with_expiration(ShortString::from(delay.as_millis().to_string()))
+}
+
 /// Tries to get the environment variable `RABBITMQ_CONNECTION_STRING` and panics if not found.
 pub fn get_rabbitmq_connection_string() -> String {
     std::env::var("RABBITMQ_CONNECTION_STRING").expect("RABBITMQ_CONNECTION_STRING must be set.")
@@ -33,18 +163,55 @@ pub async fn rabbitmq_connection() -> Result<Connection> {
     Ok(connection)
 }
 
-/// Creates a channel and a queue using the functions
-/// [rabbitmq_channel] and [rabbitmq_declare_queue].
+/// Creates a channel and a work queue using the functions
+/// [rabbitmq_channel] and [rabbitmq_declare_work_queue].
 #[tracing::instrument]
 pub async fn rabbitmq_channel_with_queue(
     conn: &Connection,
     queue_name: &str,
 ) -> Result<(Channel, Queue), anyhow::Error> {
     let channel = rabbitmq_channel(conn).await?;
-    let queue = rabbitmq_declare_queue(&channel, queue_name, FieldTable::default()).await?;
+    let queue = rabbitmq_declare_work_queue(&channel, queue_name).await?;
     Ok((channel, queue))
 }
 
+/// Declares a work queue together with its retry queue, its dead-letter queue and the [DEAD_LETTER_EXCHANGE].
+/// Queues that already exist with different arguments (e.g. from older versions) cause an error
+/// and have to be deleted first.
+pub async fn rabbitmq_declare_work_queue(channel: &Channel, queue_name: &str) -> Result<Queue, anyhow::Error> {
+    tokio::time::timeout(
+        RABBIT_MQ_TIMEOUT,
+        channel.exchange_declare(
+            DEAD_LETTER_EXCHANGE,
+            ExchangeKind::Direct,
+            ExchangeDeclareOptions::default(),
+            FieldTable::default(),
+        ),
+    )
+    .await
+    .context("Timed out while trying to declare the dead-letter exchange")?
+    .context("Failed to declare the dead-letter exchange")?;
+
+    let dead_letter_queue = dead_letter_queue_name(queue_name);
+    rabbitmq_declare_queue(channel, &dead_letter_queue, FieldTable::default()).await?;
+    tokio::time::timeout(
+        RABBIT_MQ_TIMEOUT,
+        channel.queue_bind(
+            &dead_letter_queue,
+            DEAD_LETTER_EXCHANGE,
+            queue_name,
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        ),
+    )
+    .await
+    .context("Timed out while trying to bind a dead-letter queue")?
+    .context("Failed to bind dead-letter queue")?;
+
+    rabbitmq_declare_queue(channel, &retry_queue_name(queue_name), retry_queue_arguments(queue_name)).await?;
+    rabbitmq_declare_queue(channel, queue_name, work_queue_arguments(queue_name)).await
+}
+
 /// Declares a queue on a given channel. Arguments can be provided but
 /// the [QueueDeclareOptions] are set to default.
 pub async fn rabbitmq_declare_queue(
@@ -81,6 +248,19 @@ pub async fn rabbitmq_channel(conn: &Connection) -> Result<Channel, anyhow::Erro
     Ok(channel)
 }
 
+/// Puts the channel into confirm mode, so that [publish] only returns
+/// once the broker has taken responsibility for the message.
+pub async fn rabbitmq_confirm_select(channel: &Channel) -> Result<(), anyhow::Error> {
+    tokio::time::timeout(
+        RABBIT_MQ_TIMEOUT,
+        channel.confirm_select(ConfirmSelectOptions::default()),
+    )
+    .await
+    .context("Timed out while trying to enable publisher confirms")?
+    .context("Failed to enable publisher confirms")?;
+    Ok(())
+}
+
 /// Creates a RabbitMQ consumer based on a channel, a queue name and a consumer_tag.
 /// Uses default [BasicConsumeOptions] and [FieldTable].
 pub async fn rabbitmq_consumer(
@@ -104,21 +284,104 @@ pub async fn rabbitmq_consumer(
 }
 
 /// Publishes a batch to a given queue using default [BasicPublishOptions] and [BasicProperties].
-/// Panics in case of an error.
+/// If the channel is in confirm mode (see [rabbitmq_confirm_select]), waits for the broker
+/// confirmation and returns an error if the message was rejected.
 pub async fn publish<T: Serialize>(channel: &Channel, queue_name: &str, content: &T) -> Result<()> {
     let serialized_content = &serde_json::to_vec(&content)
         .with_context(||"Serialization to json failed")?;
 
-    channel
+    publish_bytes(channel, queue_name, serialized_content, BasicProperties::default()).await
+}
+
+/// Publishes a message that failed for the given attempt to the retry queue of `queue_name`,
+/// or to its dead-letter queue if the attempt exceeds `policy.max_attempts`.
+pub async fn publish_retry<T: Serialize>(
+    channel: &Channel,
+    queue_name: &str,
+    content: &T,
+    attempt: u32,
+    policy: &RedeliveryPolicy,
+) -> Result<Redelivery> {
+    let serialized_content = &serde_json::to_vec(&content)
+        .with_context(||"Serialization to json failed")?;
+
+    if attempt > policy.max_attempts {
+        publish_bytes(channel, &dead_letter_queue_name(queue_name), serialized_content, BasicProperties::default()).await?;
+        increment_counter!("rabbitmq_dead_lettered_messages", "queue" => queue_name.to_string());
+        return Ok(Redelivery::DeadLettered);
+    }
+    let properties = retry_properties(&BasicProperties::default(), attempt, policy.delay);
+    publish_bytes(channel, &retry_queue_name(queue_name), serialized_content, properties).await?;
+    increment_counter!("rabbitmq_retried_messages", "queue" => queue_name.to_string());
+    Ok(Redelivery::Retried { attempt })
+}
+
+/// Settles a delivery of `queue_name` that failed: if it has attempts left, a copy is published
+/// to the retry queue and the delivery is acknowledged, otherwise it is rejected into the dead-letter queue.
+/// `channel` should be in confirm mode (see [rabbitmq_confirm_select]), so that the delivery is only
+/// acknowledged once the copy has been confirmed.
+pub async fn retry_or_dead_letter(
+    channel: &Channel,
+    queue_name: &str,
+    delivery: &Delivery,
+    policy: &RedeliveryPolicy,
+) -> Result<Redelivery> {
+    let attempt = delivery_attempt(&delivery.properties) + 1;
+    if attempt > policy.max_attempts {
+        dead_letter(queue_name, delivery).await?;
+        return Ok(Redelivery::DeadLettered);
+    }
+    let properties = retry_properties(&delivery.properties, attempt, policy.delay);
+    publish_bytes(channel, &retry_queue_name(queue_name), &delivery.data, properties).await?;
+    delivery.ack(BasicAckOptions::default()).await?;
+    increment_counter!("rabbitmq_retried_messages", "queue" => queue_name.to_string());
+    Ok(Redelivery::Retried { attempt })
+}
+
+/// Takes the action of `outcome` for a delivery of `queue_name`. A delivery must be settled exactly once.
+pub async fn settle(
+    channel: &Channel,
+    queue_name: &str,
+    delivery: &Delivery,
+    outcome: DeliveryOutcome,
+    policy: &RedeliveryPolicy,
+) -> Result<()> {
+    match outcome {
+        DeliveryOutcome::Ack => delivery.ack(BasicAckOptions::default()).await?,
+        DeliveryOutcome::Requeue => {
+            retry_or_dead_letter(channel, queue_name, delivery, policy).await?;
+        }
+        DeliveryOutcome::DeadLetter => dead_letter(queue_name, delivery).await?,
+    }
+    Ok(())
+}
+
+/// Rejects a delivery of `queue_name` without requeueing it, which moves it to the dead-letter queue.
+pub async fn dead_letter(queue_name: &str, delivery: &Delivery) -> Result<()> {
+    delivery
+        .nack(BasicNackOptions { multiple: false, requeue: false })
+        .await?;
+    increment_counter!("rabbitmq_dead_lettered_messages", "queue" => queue_name.to_string());
+    Ok(())
+}
+
+/// Publishes raw bytes to a given queue, see [publish].
+async fn publish_bytes(channel: &Channel, queue_name: &str, content: &[u8], properties: BasicProperties) -> Result<()> {
+    let confirmation = channel
         .basic_publish(
             "",
             queue_name,
             BasicPublishOptions::default(),
-            serialized_content,
-            BasicProperties::default(),
+            content,
+            properties,
         )
         .await
-        .context(format!("A failure happened publishing to RabbitMQ queue {}", queue_name))?;
+        .context(format!("A failure happened publishing to RabbitMQ queue {}", queue_name))?
+        .await
+        .context(format!("A failure happened confirming a publish to RabbitMQ queue {}", queue_name))?;
+
+    if confirmation.is_nack() {
+        anyhow::bail!("RabbitMQ rejected a message published to queue {}", queue_name);
+    }
     Ok(())
 }
-
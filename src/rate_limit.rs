@@ -0,0 +1,200 @@
+//! This module contains the client-side rate limiting of requests to Common Crawl.
+//! Common Crawl throttles aggressive clients with 503s, and every additional worker adds to the request rate.
+//! Requests therefore take a token from a bucket before they are sent: one bucket per process and optionally
+//! one bucket shared by all processes on a host, whose state lives in a file guarded by a file lock.
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use metrics::{histogram, increment_counter};
+
+/// Command line arguments to configure a [RateLimiter], shared by all binaries that download data.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RateLimitArgs {
+    /// Maximum number of requests per second this process sends to Common Crawl. Unlimited if not set.
+    #[arg(long("rate-limit"))]
+    pub requests_per_second: Option<f64>,
+
+    /// Maximum number of requests per second all processes using `--shared-rate-limit-file` send together.
+    #[arg(long("shared-rate-limit"))]
+    pub shared_requests_per_second: Option<f64>,
+
+    /// File holding the state of the shared rate limit. All processes on a host have to use the same file.
+    #[arg(long("shared-rate-limit-file"), default_value = "./data/rate_limit.state")]
+    pub shared_state_file: PathBuf,
+
+    /// Number of requests that may be sent at once after a quiet period.
+    #[arg(long("rate-limit-burst"), default_value_t = 1.0)]
+    pub burst: f64,
+}
+
+impl RateLimitArgs {
+    pub fn limiter(&self) -> anyhow::Result<RateLimiter> {
+        for rate in [self.requests_per_second, self.shared_requests_per_second].into_iter().flatten() {
+            anyhow::ensure!(rate > 0.0, "Rate limits need to be positive, got {}", rate);
+        }
+        anyhow::ensure!(self.burst >= 1.0, "--rate-limit-burst needs to be at least 1");
+
+        let mut limiter = match self.requests_per_second {
+            Some(rate) => RateLimiter::new(rate, self.burst),
+            None => RateLimiter::unlimited(),
+        };
+        if let Some(rate) = self.shared_requests_per_second {
+            limiter = limiter.with_shared(&self.shared_state_file, rate, self.burst)?;
+        }
+        Ok(limiter)
+    }
+}
+
+/// A token bucket holding up to `burst` tokens, refilled with `rate` tokens per second.
+/// Tokens may be reserved in advance, which lets the balance become negative;
+/// the caller then waits until its token has been refilled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBucket {
+    pub rate: f64,
+    pub burst: f64,
+    pub tokens: f64,
+    /// Time of the last refill in seconds, relative to an arbitrary but fixed point in time.
+    pub updated: f64,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: f64, now: f64) -> Self {
+        TokenBucket {
+            rate,
+            burst,
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    /// Takes a token and returns how long the caller has to wait before it may use it.
+    pub fn reserve(&mut self, now: f64) -> Duration {
+        let elapsed = (now - self.updated).max(0.0);
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst) - 1.0;
+        self.updated = self.updated.max(now);
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Limits the rate of requests of this process and, optionally, of all processes sharing a state file.
+#[derive(Debug)]
+pub struct RateLimiter {
+    local: Option<Mutex<TokenBucket>>,
+    shared: Option<SharedBucket>,
+    started: Instant,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::unlimited()
+    }
+}
+
+impl RateLimiter {
+    pub fn unlimited() -> Self {
+        RateLimiter {
+            local: None,
+            shared: None,
+            started: Instant::now(),
+        }
+    }
+
+    /// A limiter allowing `rate` requests per second in this process.
+    pub fn new(rate: f64, burst: f64) -> Self {
+        RateLimiter {
+            local: Some(Mutex::new(TokenBucket::new(rate, burst, 0.0))),
+            ..RateLimiter::unlimited()
+        }
+    }
+
+    /// Additionally limits the rate of all processes that share the state file at `path` to `rate` requests per second.
+    pub fn with_shared(mut self, path: &Path, rate: f64, burst: f64) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.shared = Some(SharedBucket {
+            path: path.to_path_buf(),
+            rate,
+            burst,
+        });
+        Ok(self)
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn acquire(&self) -> anyhow::Result<()> {
+        let mut wait = Duration::ZERO;
+        if let Some(local) = &self.local {
+            let now = self.started.elapsed().as_secs_f64();
+            wait = local.lock().unwrap().reserve(now);
+        }
+        if let Some(shared) = &self.shared {
+            let shared = shared.clone();
+            let shared_wait = tokio::task::spawn_blocking(move || shared.reserve()).await??;
+            wait = wait.max(shared_wait);
+        }
+
+        histogram!("download_rate_limit_wait_seconds", wait.as_secs_f64());
+        if !wait.is_zero() {
+            increment_counter!("download_rate_limited");
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+}
+
+/// A token bucket whose state is stored in a file, so that several processes can share it.
+/// The file is locked exclusively while a token is taken.
+#[derive(Debug, Clone)]
+struct SharedBucket {
+    path: PathBuf,
+    rate: f64,
+    burst: f64,
+}
+
+impl SharedBucket {
+    fn reserve(&self) -> anyhow::Result<Duration> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open rate limit state {}", self.path.display()))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock rate limit state {}", self.path.display()))?;
+
+        // wall clock time, as the state is shared between processes
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        let mut bucket = read_state(&mut file)
+            .map(|(tokens, updated)| TokenBucket {
+                rate: self.rate,
+                burst: self.burst,
+                tokens,
+                updated,
+            })
+            .unwrap_or_else(|| TokenBucket::new(self.rate, self.burst, now));
+        let wait = bucket.reserve(now);
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{} {}", bucket.tokens, bucket.updated)?;
+        // the lock is released when the file is closed
+        Ok(wait)
+    }
+}
+
+/// Reads the `<tokens> <updated>` state; a missing or corrupt state starts a full bucket.
+fn read_state(file: &mut File) -> Option<(f64, f64)> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    let (tokens, updated) = content.trim().split_once(' ')?;
+    Some((tokens.parse().ok()?, updated.parse().ok()?))
+}
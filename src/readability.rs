@@ -0,0 +1,71 @@
+//! This module contains a pure-Rust extraction backend based on `dom_smoothie`, a port of Mozilla's Readability.
+//! It scores the elements of a page to find the main content and drops navigation, ads and other boilerplate.
+use async_trait::async_trait;
+use dom_smoothie::{Config, Readability, ReadabilityError, TextMode};
+
+use crate::extraction_pool::ExtractionPool;
+use crate::extractor::{DocumentMetadata, ExtractedDocument, ExtractionOptions, Extractor, OutputFormat};
+
+/// Extracts text with Readability on the extraction pool.
+#[derive(Debug, Clone)]
+pub struct ReadabilityExtractor {
+    pool: ExtractionPool,
+    text_mode: TextMode,
+}
+
+impl ReadabilityExtractor {
+    /// Readability only supports the output format of the options; the other options are specific to trafilatura.
+    pub fn new(pool: ExtractionPool, options: &ExtractionOptions) -> anyhow::Result<Self> {
+        Ok(ReadabilityExtractor {
+            pool,
+            text_mode: text_mode(options.output_format)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Extractor for ReadabilityExtractor {
+    async fn extract(&self, html: String) -> anyhow::Result<Option<ExtractedDocument>> {
+        let text_mode = self.text_mode;
+        self.pool.run(move || extract(&html, text_mode)).await
+    }
+}
+
+fn text_mode(format: OutputFormat) -> anyhow::Result<TextMode> {
+    match format {
+        // keeps paragraphs apart, like the output of trafilatura
+        OutputFormat::Txt => Ok(TextMode::Formatted),
+        OutputFormat::Markdown => Ok(TextMode::Markdown),
+        OutputFormat::Xml => anyhow::bail!("The readability backend does not support XML output"),
+    }
+}
+
+/// Extract text and metadata from `html` and return the extracted document if successful.
+/// Returns `Ok(None)` if no main content was found.
+pub fn extract(html: &str, text_mode: TextMode) -> anyhow::Result<Option<ExtractedDocument>> {
+    let config = Config {
+        text_mode,
+        ..Config::default()
+    };
+    let mut readability = Readability::new(html, None, Some(config))?;
+    let article = match readability.parse() {
+        Ok(article) => article,
+        Err(ReadabilityError::GrabFailed) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let text = article.text_content.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ExtractedDocument {
+        text: text.to_string(),
+        metadata: DocumentMetadata {
+            title: Some(article.title).filter(|title| !title.is_empty()),
+            author: article.byline,
+            date: article.published_time,
+            sitename: article.site_name,
+            description: article.excerpt,
+            categories: Vec::new(),
+        },
+    }))
+}
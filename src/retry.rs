@@ -0,0 +1,152 @@
+//! This module contains the retry policy for downloads from Common Crawl.
+//! Common Crawl answers with 503 or 429 when it is overloaded, so transient failures
+//! are retried with exponential backoff and jitter instead of aborting the batcher or the worker.
+use std::future::Future;
+use std::time::Duration;
+
+use metrics::increment_counter;
+use rand::Rng;
+
+use crate::commoncrawl::HttpStatusError;
+
+/// Command line arguments to configure a [RetryPolicy], shared by all binaries that download data.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RetryArgs {
+    /// How often a download is attempted before giving up.
+    #[arg(long("retry-max-attempts"), default_value_t = 5,
+    value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_attempts: u32,
+
+    /// Backoff before the first retry in milliseconds; doubles with every further retry.
+    #[arg(long("retry-initial-backoff-ms"), default_value_t = 500)]
+    pub initial_backoff_ms: u64,
+
+    /// Upper bound of the backoff between two attempts in milliseconds.
+    #[arg(long("retry-max-backoff-ms"), default_value_t = 30_000)]
+    pub max_backoff_ms: u64,
+}
+
+impl RetryArgs {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_backoff_ms),
+        }
+    }
+}
+
+/// How often and how long to wait before an operation is retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The backoff after the given (1-based) failed attempt: exponential, capped at `max_backoff`,
+    /// with a random jitter of up to half of the delay so that workers do not retry in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let delay = exponential.min(self.max_backoff);
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        delay.mul_f64(1.0 - jitter)
+    }
+
+    /// The delay after the given (1-based) failed attempt: the backoff, or the delay requested by the server
+    /// if that is longer, but never more than `max_backoff`.
+    pub fn retry_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self.backoff(attempt);
+        retry_after.map_or(backoff, |requested| requested.max(backoff).min(self.max_backoff))
+    }
+}
+
+/// Runs `operation` until it succeeds, fails with a non-retryable error or `policy.max_attempts` is reached.
+/// `description` is only used for logging.
+/// If the server sent a `Retry-After` header, the next attempt waits that long, up to `policy.max_backoff`.
+pub async fn with_retry<T, F, Fut>(policy: &RetryPolicy, description: &str, mut operation: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        let err = match operation().await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        let Some(retry_after) = retryable(&err) else {
+            increment_counter!("download_failures");
+            return Err(err);
+        };
+        if attempt >= policy.max_attempts {
+            increment_counter!("download_failures");
+            return Err(err.context(format!("Giving up on {} after {} attempts", description, attempt)));
+        }
+
+        let delay = policy.retry_delay(attempt, retry_after);
+        tracing::warn!(
+            "Attempt {} of {} failed: {:#}; retrying in {:?}",
+            attempt,
+            description,
+            err,
+            delay
+        );
+        increment_counter!("download_retries");
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Decides whether an error is transient. Returns `None` if the error should not be retried,
+/// otherwise the delay requested by the server (if any).
+pub fn retryable(err: &anyhow::Error) -> Option<Option<Duration>> {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<HttpStatusError>() {
+            let transient = e.status == reqwest::StatusCode::TOO_MANY_REQUESTS || e.status.is_server_error();
+            return transient.then_some(e.retry_after);
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if transient_reqwest_error(e) {
+                return Some(None);
+            }
+        }
+        if let Some(minio::s3::error::Error::ServerError(_)) = cause.downcast_ref::<minio::s3::error::Error>() {
+            return Some(None);
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            // streamed response bodies wrap their errors in io errors, whose `source` skips the wrapped error
+            if let Some(e) = e.get_ref().and_then(|inner| inner.downcast_ref::<reqwest::Error>()) {
+                if transient_reqwest_error(e) {
+                    return Some(None);
+                }
+            }
+            // no `UnexpectedEof`: a truncated gzip member or a short local read fails the same way every time
+            use std::io::ErrorKind::*;
+            if matches!(e.kind(), ConnectionReset | ConnectionAborted | BrokenPipe | TimedOut) {
+                return Some(None);
+            }
+        }
+    }
+    None
+}
+
+fn transient_reqwest_error(e: &reqwest::Error) -> bool {
+    // a connection failure while reading a streamed body is reported as decode error;
+    // a body that is not valid JSON is one as well, but fails the same way every time
+    let invalid_json = std::error::Error::source(e).is_some_and(|source| source.is::<serde_json::Error>());
+    e.is_timeout() || e.is_connect() || e.is_body() || e.is_request() || (e.is_decode() && !invalid_json)
+}
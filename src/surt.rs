@@ -0,0 +1,88 @@
+//! This module contains helpers to select parts of a crawl by SURT key.
+//! Both the cluster.idx file and the cdx files are sorted by SURT, so a SURT range
+//! maps to a contiguous run of cdx chunks, which we find by binary search.
+use serde::Serialize;
+
+use crate::commoncrawl::ClusterIdxEntry;
+
+/// A half-open range `[start, end)` of SURT keys. An `end` of `None` means unbounded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SurtRange {
+    pub start: String,
+    pub end: Option<String>,
+}
+
+impl SurtRange {
+    /// The range of all SURT keys starting with `prefix`, e.g. `com,example)/` or `de,`.
+    pub fn prefix(prefix: &str) -> SurtRange {
+        SurtRange {
+            start: prefix.to_string(),
+            end: prefix_successor(prefix),
+        }
+    }
+
+    /// Returns true if `surt_url` lies within this range.
+    pub fn contains(&self, surt_url: &str) -> bool {
+        surt_url >= self.start.as_str() && self.end.as_deref().is_none_or(|end| surt_url < end)
+    }
+}
+
+/// The smallest string that is greater than all strings starting with `prefix`,
+/// or `None` if there is no such string.
+fn prefix_successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        // skip over the surrogate range, which has no valid chars
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+/// Returns true if `surt_url` lies within any of `ranges`. An empty list of ranges selects everything.
+pub fn in_ranges(ranges: &[SurtRange], surt_url: &str) -> bool {
+    ranges.is_empty() || ranges.iter().any(|range| range.contains(surt_url))
+}
+
+/// Keeps only the cluster.idx entries whose cdx chunk may contain SURT keys within `ranges`.
+/// Chunk `i` holds the keys from its own SURT key up to (and including) the SURT key of chunk `i + 1`,
+/// so the chunks overlapping a range are found by binary search on the SURT keys of `idx`.
+/// An empty list of ranges selects all entries.
+pub fn select_chunks(idx: Vec<ClusterIdxEntry>, ranges: &[SurtRange]) -> Vec<ClusterIdxEntry> {
+    if ranges.is_empty() {
+        return idx;
+    }
+
+    let mut selected = vec![false; idx.len()];
+    for range in ranges {
+        let first = idx
+            .partition_point(|e| e.surt_url.as_str() < range.start.as_str())
+            .saturating_sub(1);
+        let end = match &range.end {
+            Some(end) => idx.partition_point(|e| e.surt_url.as_str() < end.as_str()),
+            None => idx.len(),
+        };
+        for is_selected in selected.iter_mut().take(end).skip(first) {
+            *is_selected = true;
+        }
+    }
+
+    idx.into_iter()
+        .zip(selected)
+        .filter_map(|(entry, is_selected)| is_selected.then_some(entry))
+        .collect()
+}
+
+/// The SURT range covered by the first `chunks` entries of `idx`, or `None` if `idx` has no more entries.
+/// Limiting several crawls to the range of one of them makes all of them cover the same URLs,
+/// whereas taking the first `chunks` entries of every crawl would not, as their chunks start at different keys.
+pub fn chunk_limit_range(idx: &[ClusterIdxEntry], chunks: usize) -> Option<SurtRange> {
+    let first_excluded = idx.get(chunks)?;
+    Some(SurtRange {
+        start: String::new(),
+        end: Some(first_excluded.surt_url.clone()),
+    })
+}
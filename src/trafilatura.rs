@@ -1,6 +1,7 @@
 //! This module contains the Python and PyO3 code to be able to use trafilatura
 //! from Rust.
 
+use async_trait::async_trait;
 use autometrics::autometrics;
 use once_cell::sync::Lazy;
 use pyo3::ffi::c_str;
@@ -9,6 +10,9 @@ use pyo3::{
     Py, PyAny, PyObject, Python,
 };
 
+use crate::extraction_pool::ExtractionPool;
+use crate::extractor::{ExtractedDocument, ExtractionOptions, Extractor};
+
 static PYTHON_EXTRACT_FUNCTION: Lazy<Py<PyAny>> = Lazy::new(|| {
     Python::with_gil(move |py| -> PyObject {
         tracing::info!(
@@ -26,23 +30,52 @@ static PYTHON_EXTRACT_FUNCTION: Lazy<Py<PyAny>> = Lazy::new(|| {
         )
         .expect("Failed to load Python module");
         let extract_function = module
-            .getattr("extract_text")
-            .expect("Failed to get extract_text function");
+            .getattr("extract_document")
+            .expect("Failed to get extract_document function");
         let extract_function = extract_function.into();
         tracing::info!("Loaded Python trafilatura.");
         extract_function
     })
 });
 
-/// Extract text from `html` and return the extracted
-/// text as string if successful.
+/// Extract text and metadata from `html` with the given options and return the extracted
+/// document if successful.
 /// Might return `Ok(None)` if text extraction was not successful.
 #[autometrics]
-pub fn extract(html: &str) -> Result<Option<String>, anyhow::Error> {
-    Python::with_gil(move |py| -> Result<Option<String>, anyhow::Error> {
+pub fn extract(html: &str, options: &ExtractionOptions) -> Result<Option<ExtractedDocument>, anyhow::Error> {
+    let options = serde_json::to_string(options)?;
+    let document = Python::with_gil(move |py| -> Result<Option<String>, anyhow::Error> {
         PYTHON_EXTRACT_FUNCTION
-            .call1(py, (html,))?
+            .call1(py, (html, options))?
             .extract(py)
             .map_err(Into::into)
-    })
+    })?;
+    document
+        .map(|document| serde_json::from_str(&document))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Runs [extract] on the extraction pool.
+#[derive(Debug, Clone)]
+pub struct InProcessTrafilatura {
+    pool: ExtractionPool,
+    options: ExtractionOptions,
+}
+
+impl InProcessTrafilatura {
+    pub fn new(pool: ExtractionPool, options: &ExtractionOptions) -> Self {
+        InProcessTrafilatura {
+            pool,
+            options: options.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Extractor for InProcessTrafilatura {
+    async fn extract(&self, html: String) -> anyhow::Result<Option<ExtractedDocument>> {
+        let options = self.options.clone();
+        self.pool.run(move || extract(&html, &options)).await
+    }
 }
@@ -1,10 +1,12 @@
 use anyhow::Context;
 use metrics::increment_counter;
-use minio::s3::args::PutObjectArgs;
+use minio::s3::args::{PutObjectArgs, StatObjectArgs};
 use minio::s3::client::Client;
+use minio::s3::error::Error as MinioError;
 use minio::s3::utils::Multimap;
 use sha2::{Sha256, Digest};
 use crate::commoncrawl::CdxFileContext;
+use crate::extractor::DocumentMetadata;
 
 pub fn calculate_hash(to_be_hashed: &str) -> String {
     let mut hasher = Sha256::new();
@@ -12,9 +14,32 @@ pub fn calculate_hash(to_be_hashed: &str) -> String {
     format!("{:X}", hasher.finalize())
 }
 
+/// User metadata holding the SHA-256 of an uploaded document, used to detect repeated uploads.
+const CONTENT_HASH_METADATA: &str = "content-sha256";
+
+/// The name of the object a document is stored as: one object per WARC file and target URI,
+/// so that uploading the same document again overwrites it instead of creating a duplicate.
+pub fn object_name(entry: &CdxFileContext) -> String {
+    format!("{}/{}.json", &entry.filename, calculate_hash(&entry.target_uri))
+}
+
+/// The headers that store the publication date and the site name of a document as user metadata of its object
+/// (`published-date` and `sitename`).
+pub fn metadata_headers(metadata: &DocumentMetadata) -> Vec<(String, String)> {
+    [("published-date", &metadata.date), ("sitename", &metadata.sitename)]
+        .into_iter()
+        // object metadata is sent as HTTP headers, which only allow (printable) ASCII
+        .filter_map(|(key, value)| {
+            let value = value.as_ref().filter(|v| v.chars().all(|c| c.is_ascii_graphic() || c == ' '))?;
+            Some((format!("x-amz-meta-{key}"), value.clone()))
+        })
+        .collect()
+}
+
+/// Uploads a document to the bucket. Uploading the same document again (e.g. after a message was redelivered)
+/// does not create a duplicate, and is skipped if the stored object already has the same content.
 pub async fn upload_file_to_minio(client: &Client, entry: &CdxFileContext, s3_bucket: &str) -> anyhow::Result<()> {
-    let file_name_hash = calculate_hash(&entry.filename);
-    let file_name = format!("{}/{}.json", &entry.filename, file_name_hash);
+    let file_name = object_name(entry);
 
     tracing::info!(
         "File content for uri {} received and ready for storage",
@@ -22,14 +47,25 @@ pub async fn upload_file_to_minio(client: &Client, entry: &CdxFileContext, s3_bu
     );
     
     let bytes = &serde_json::to_vec(&entry)?;
+    let content_hash = calculate_hash(std::str::from_utf8(bytes)?);
+    if stored_content_hash(client, s3_bucket, &file_name).await?.as_ref() == Some(&content_hash) {
+        tracing::info!("File `{}` is already stored in bucket `{}`; skipping upload.", file_name, s3_bucket);
+        increment_counter!("saver_file_already_uploaded");
+        return Ok(());
+    }
+
     let read: &mut dyn std::io::Read = &mut bytes.as_slice();
     let object_size = Some(bytes.len());
 
     // prepare file loading
-    let put_args = &mut PutObjectArgs::new(&s3_bucket, &file_name, read, object_size, None)?;
-    // adding original url as metadata
+    let put_args = &mut PutObjectArgs::new(s3_bucket, &file_name, read, object_size, None)?;
+    // adding original url, and publication date and site for filtering, as metadata
     let mut map = Multimap::new();
     map.insert("x-original-url".to_string(), entry.target_uri.to_string());
+    map.insert(format!("x-amz-meta-{CONTENT_HASH_METADATA}"), content_hash);
+    for (key, value) in metadata_headers(&entry.metadata) {
+        map.insert(key, value);
+    }
     put_args.user_metadata = Some(&map);
 
     client.put_object(put_args).await.with_context(|| {
@@ -48,3 +84,12 @@ pub async fn upload_file_to_minio(client: &Client, entry: &CdxFileContext, s3_bu
 
     Ok(())
 }
+
+/// Returns the content hash of an already stored object, or `None` if there is no such object.
+async fn stored_content_hash(client: &Client, s3_bucket: &str, file_name: &str) -> anyhow::Result<Option<String>> {
+    match client.stat_object(&StatObjectArgs::new(s3_bucket, file_name)?).await {
+        Ok(response) => Ok(response.user_metadata.get(CONTENT_HASH_METADATA).cloned()),
+        Err(MinioError::S3Error(error)) if error.code == "NoSuchKey" => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to look up object {} in MinIO", file_name)),
+    }
+}
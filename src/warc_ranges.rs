@@ -0,0 +1,103 @@
+//! This module contains the coalescing of WARC range requests.
+//! The entries of a batch often point into the same WARC file at nearby offsets. Instead of sending one
+//! range request per entry, the worker downloads one range covering several records and splits it back
+//! into the records, each of which is a gzip member of its own.
+use std::collections::BTreeMap;
+
+use crate::commoncrawl::CdxEntry;
+
+/// Command line arguments to configure how range requests are coalesced.
+#[derive(clap::Args, Debug, Clone)]
+pub struct CoalesceArgs {
+    /// Records of the same WARC file that are at most this many bytes apart are downloaded with a single request.
+    /// The bytes between them are downloaded and discarded.
+    #[arg(long("coalesce-max-gap"), default_value_t = 64 * 1024)]
+    pub max_gap: usize,
+
+    /// Upper bound of the size of a coalesced request in bytes. Larger records are still downloaded on their own.
+    #[arg(long("coalesce-max-bytes"), default_value_t = 8 * 1024 * 1024)]
+    pub max_bytes: usize,
+}
+
+/// A record within a [CoalescedRange].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeMember {
+    /// Position of the entry in the batch.
+    pub index: usize,
+    /// Offset of the record relative to the start of the range.
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// A single range request covering one or more records of a WARC file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoalescedRange {
+    pub filename: String,
+    pub offset: usize,
+    pub length: usize,
+    pub members: Vec<RangeMember>,
+}
+
+impl CoalescedRange {
+    /// Returns the bytes of a member from the downloaded bytes of the whole range.
+    pub fn member_data<'a>(&self, member: &RangeMember, data: &'a [u8]) -> anyhow::Result<&'a [u8]> {
+        data.get(member.offset..member.offset + member.length).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Range {} at offset {} returned {} bytes, record at {} needs {}",
+                self.filename,
+                self.offset,
+                data.len(),
+                member.offset,
+                member.length
+            )
+        })
+    }
+
+    fn end(&self) -> usize {
+        self.offset + self.length
+    }
+}
+
+/// Groups the entries of a batch by WARC file and merges ranges that overlap or are at most `max_gap` bytes apart,
+/// as long as the merged range does not exceed `max_bytes`.
+/// Ranges are ordered by file name and offset.
+pub fn coalesce_ranges(entries: &[CdxEntry], max_gap: usize, max_bytes: usize) -> Vec<CoalescedRange> {
+    let mut by_file: BTreeMap<&str, Vec<(usize, &CdxEntry)>> = BTreeMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        by_file.entry(&entry.metadata.filename).or_default().push((index, entry));
+    }
+
+    let mut ranges = Vec::new();
+    for (filename, mut file_entries) in by_file {
+        file_entries.sort_by_key(|(_, entry)| entry.metadata.offset);
+
+        let mut current: Option<CoalescedRange> = None;
+        for (index, entry) in file_entries {
+            let (offset, length) = (entry.metadata.offset, entry.metadata.length);
+            if let Some(range) = current.as_mut() {
+                let end = range.end().max(offset + length);
+                if offset <= range.end() + max_gap && end - range.offset <= max_bytes {
+                    range.length = end - range.offset;
+                    range.members.push(RangeMember {
+                        index,
+                        offset: offset - range.offset,
+                        length,
+                    });
+                    continue;
+                }
+            }
+            ranges.extend(current.replace(CoalescedRange {
+                filename: filename.to_string(),
+                offset,
+                length,
+                members: vec![RangeMember {
+                    index,
+                    offset: 0,
+                    length,
+                }],
+            }));
+        }
+        ranges.extend(current);
+    }
+    ranges
+}
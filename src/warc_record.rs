@@ -0,0 +1,131 @@
+//! This module contains the handling of the WARC records downloaded by the worker.
+//! Every record either yields the HTML of a response or a [SkipReason], so that malformed or
+//! unwanted records are counted and skipped instead of crashing the worker.
+use std::ops::RangeInclusive;
+
+use thiserror::Error;
+use warc::{RawRecordHeader, WarcHeader, WarcReader};
+
+use crate::http_response::HttpResponse;
+
+/// Extracted texts with a length (in bytes) outside of this range are not stored.
+pub const CONTENT_LENGTH_RANGE: RangeInclusive<usize> = 500..=1_000_000;
+
+/// MIME types that are passed to the extractor.
+const HTML_MIME_TYPES: [&str; 2] = ["text/html", "application/xhtml+xml"];
+
+/// Why a record did not result in a stored document.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SkipReason {
+    #[error("invalid WARC record: {0}")]
+    InvalidRecord(String),
+    #[error("missing {0} header")]
+    MissingHeader(&'static str),
+    #[error("not a response record but {0}")]
+    NotAResponse(String),
+    #[error("truncated record ({0})")]
+    Truncated(String),
+    #[error("invalid HTTP response: {0}")]
+    InvalidHttpResponse(String),
+    #[error("not HTML but {0}")]
+    NotHtml(String),
+    #[error("no content could be extracted")]
+    NoContent,
+    #[error("extracted content of {0} bytes is too short")]
+    TooShort(usize),
+    #[error("extracted content of {0} bytes is too long")]
+    TooLong(usize),
+}
+
+impl SkipReason {
+    /// A short name of the reason, used as metrics label.
+    pub fn label(&self) -> &'static str {
+        match self {
+            SkipReason::InvalidRecord(_) => "invalid_record",
+            SkipReason::MissingHeader(_) => "missing_header",
+            SkipReason::NotAResponse(_) => "not_a_response",
+            SkipReason::Truncated(_) => "truncated",
+            SkipReason::InvalidHttpResponse(_) => "invalid_http_response",
+            SkipReason::NotHtml(_) => "not_html",
+            SkipReason::NoContent => "no_content",
+            SkipReason::TooShort(_) => "too_short",
+            SkipReason::TooLong(_) => "too_long",
+        }
+    }
+}
+
+/// The HTML of a response record, decoded to UTF-8.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseRecord {
+    pub target_uri: String,
+    pub html: String,
+}
+
+/// Reads the records of an uncompressed WARC file. A record that cannot be read ends the iteration,
+/// as the position of the next record is unknown.
+pub fn read_records<'a>(
+    data: &'a [u8],
+    fallback_charset: Option<&'a str>,
+) -> impl Iterator<Item = Result<ResponseRecord, SkipReason>> + 'a {
+    let mut records = WarcReader::new(data).iter_raw_records();
+    let mut failed = false;
+    std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        match records.next()? {
+            Ok((header, body)) => Some(response_html(&header, &body, fallback_charset)),
+            Err(e) => {
+                failed = true;
+                Some(Err(SkipReason::InvalidRecord(e.to_string())))
+            }
+        }
+    })
+}
+
+/// Returns the target URI and the HTML of a `response` record.
+/// `fallback_charset` is used if neither the HTTP headers nor the HTML declare a charset.
+pub fn response_html(
+    header: &RawRecordHeader,
+    body: &[u8],
+    fallback_charset: Option<&str>,
+) -> Result<ResponseRecord, SkipReason> {
+    let warc_type = header_value(header, WarcHeader::WarcType).ok_or(SkipReason::MissingHeader("WARC-Type"))?;
+    if warc_type != "response" {
+        return Err(SkipReason::NotAResponse(warc_type));
+    }
+    let target_uri =
+        header_value(header, WarcHeader::TargetURI).ok_or(SkipReason::MissingHeader("WARC-Target-URI"))?;
+    if let Some(reason) = header_value(header, WarcHeader::Truncated) {
+        return Err(SkipReason::Truncated(reason));
+    }
+    let response = HttpResponse::parse(body).map_err(|e| SkipReason::InvalidHttpResponse(e.to_string()))?;
+    // without a Content-Type header, we let the extractor decide
+    if let Some(mime) = response.mime_type() {
+        if !HTML_MIME_TYPES.contains(&mime.as_str()) {
+            return Err(SkipReason::NotHtml(mime));
+        }
+    }
+    let html = response
+        .text(fallback_charset)
+        .map_err(|e| SkipReason::InvalidHttpResponse(e.to_string()))?;
+    Ok(ResponseRecord { target_uri, html })
+}
+
+/// Checks that the length of an extracted text is within [CONTENT_LENGTH_RANGE].
+pub fn check_content_length(content: &str) -> Result<(), SkipReason> {
+    let len = content.len();
+    if len < *CONTENT_LENGTH_RANGE.start() {
+        Err(SkipReason::TooShort(len))
+    } else if len > *CONTENT_LENGTH_RANGE.end() {
+        Err(SkipReason::TooLong(len))
+    } else {
+        Ok(())
+    }
+}
+
+fn header_value(header: &RawRecordHeader, name: WarcHeader) -> Option<String> {
+    let value = header.as_ref().get(&name)?;
+    let value = String::from_utf8_lossy(value).trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
@@ -0,0 +1,144 @@
+mod common;
+
+#[cfg(test)]
+mod batch_processing_tests {
+    use std::sync::Mutex;
+
+    use pipeline::batch_processing::{process_ranges, BatchReport, FailedEntry};
+    use pipeline::warc_ranges::{CoalescedRange, RangeMember};
+
+    use crate::common::entry;
+
+    /// A range of `members` records of 2 bytes each, the first one being entry `first_index` of the batch.
+    fn range(filename: &str, first_index: usize, members: usize) -> CoalescedRange {
+        CoalescedRange {
+            filename: filename.to_string(),
+            offset: 0,
+            length: 2 * members,
+            members: (0..members)
+                .map(|i| RangeMember {
+                    index: first_index + i,
+                    offset: 2 * i,
+                    length: 2,
+                })
+                .collect(),
+        }
+    }
+
+    /// The bytes of a range as downloaded: record `i` consists of the bytes `[i, i]`.
+    fn data(range: &CoalescedRange) -> Vec<u8> {
+        range.members.iter().flat_map(|m| [m.index as u8; 2]).collect()
+    }
+
+    #[tokio::test]
+    async fn failing_record_does_not_fail_its_siblings() {
+        let processed = Mutex::new(Vec::new());
+
+        let report = process_ranges(
+            3,
+            vec![range("a.warc.gz", 0, 3)],
+            2,
+            2,
+            |range| async move { Ok(data(&range)) },
+            |index, record| {
+                let processed = &processed;
+                async move {
+                    anyhow::ensure!(index != 1, "extraction failed");
+                    processed.lock().unwrap().push((index, record));
+                    Ok(())
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(
+            report.failed,
+            vec![FailedEntry {
+                index: 1,
+                reason: "extraction failed".to_string()
+            }]
+        );
+        let mut processed = processed.into_inner().unwrap();
+        processed.sort();
+        assert_eq!(processed, vec![(0, vec![0, 0]), (2, vec![2, 2])]);
+        assert!(!report.all_failed());
+    }
+
+    #[tokio::test]
+    async fn failed_download_only_fails_the_members_of_its_range() {
+        let report = process_ranges(
+            4,
+            vec![range("a.warc.gz", 0, 2), range("b.warc.gz", 2, 2)],
+            2,
+            2,
+            |range| async move {
+                anyhow::ensure!(range.filename != "b.warc.gz", "503 Service Unavailable");
+                Ok(data(&range))
+            },
+            |_, _| async { Ok(()) },
+        )
+        .await;
+
+        let failed: Vec<usize> = report.failed.iter().map(|failed| failed.index).collect();
+        assert_eq!(failed, vec![2, 3]);
+        assert!(report.failed.iter().all(|failed| failed.reason == "503 Service Unavailable"));
+    }
+
+    #[tokio::test]
+    async fn short_download_only_fails_the_missing_records() {
+        let report = process_ranges(
+            2,
+            vec![range("a.warc.gz", 0, 2)],
+            1,
+            1,
+            |range| async move { Ok(data(&range)[..2].to_vec()) },
+            |_, _| async { Ok(()) },
+        )
+        .await;
+
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].index, 1);
+    }
+
+    #[tokio::test]
+    async fn all_failed_when_every_entry_failed() {
+        let report = process_ranges(
+            2,
+            vec![range("a.warc.gz", 0, 2)],
+            1,
+            1,
+            |_| async { anyhow::bail!("connection refused") },
+            |_, _| async { Ok(()) },
+        )
+        .await;
+
+        assert!(report.all_failed());
+        assert!(!BatchReport::default().all_failed());
+    }
+
+    #[test]
+    fn failed_entries_are_retried_one_by_one() {
+        let batch = vec![entry("a.warc.gz", 0, 10), entry("b.warc.gz", 0, 20), entry("c.warc.gz", 0, 30)];
+        let report = BatchReport {
+            entries: 3,
+            failed: vec![
+                FailedEntry {
+                    index: 0,
+                    reason: "first".to_string(),
+                },
+                FailedEntry {
+                    index: 2,
+                    reason: "third".to_string(),
+                },
+            ],
+        };
+
+        let retries = report.retry_batches(&batch);
+
+        let filenames: Vec<Vec<&str>> = retries
+            .iter()
+            .map(|retry| retry.iter().map(|entry| entry.metadata.filename.as_str()).collect())
+            .collect();
+        assert_eq!(filenames, vec![vec!["a.warc.gz"], vec!["c.warc.gz"]]);
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
-    use pipeline::commoncrawl::{parse_cdx_line, parse_cluster_idx};
+    use pipeline::commoncrawl::{
+        parse_cdx_line, parse_cluster_idx, CdxEntry, IndexParseError, ParseErrorPolicy,
+    };
 
 
     #[test]
@@ -8,17 +10,99 @@ mod tests {
         let content = r#"0,100,22,165)/ 20240722120756 {"url": "http://165.22.100.0/", "mime": "text/html", "mime-detected": "text/html", "status": "301", "digest": "DCNYNIFG5SBRCVS5PCUY4YY2UM2WAQ4R", "length": "689", "offset": "3499", "filename": "crawl-data/CC-MAIN-2024-30/segments/1720763517846.73/crawldiagnostics/CC-MAIN-20240722095039-20240722125039-00443.warc.gz", "redirect": "https://157.245.55.71/"}
 0,100,22,165)/robots.txt 20240722120755 {"url": "http://165.22.100.0/robots.txt", "mime": "text/html", "mime-detected": "text/html", "status": "301", "digest": "LYEE2BXON4MCQCP5FDVDNILOWBKCZZ6G", "length": "700", "offset": "4656", "filename": "crawl-data/CC-MAIN-2024-30/segments/1720763517846.73/robotstxt/CC-MAIN-20240722095039-20240722125039-00410.warc.gz", "redirect": "https://157.245.55.71/robots.txt"}
 0,100,59,139)/ 20240723213521 {"url": "https://139.59.100.0/", "mime": "text/html", "mime-detected": "text/html", "status": "200", "digest": "5JOQMMSNM6N7UCLGGYXDSPSB3FYAQS2C", "length": "16650", "offset": "64016172", "filename": "crawl-data/CC-MAIN-2024-30/segments/1720763518115.82/warc/CC-MAIN-20240723194208-20240723224208-00279.warc.gz", "charset": "UTF-8", "languages": "ind,eng"}"#;
-        let cdx: Vec<_> = content.lines().map(parse_cdx_line).collect();
+        let cdx: Vec<_> = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| parse_cdx_line(line, i + 1))
+            .collect::<Result<_, _>>()
+            .unwrap();
         assert_eq!(cdx.len(), 3);
     }
 
+    #[test]
+    fn can_parse_all_cdx_metadata_fields() {
+        let line = r#"0,100,22,165)/ 20240722120756 {"url": "http://165.22.100.0/", "mime": "text/html", "mime-detected": "application/xhtml+xml", "status": "301", "digest": "DCNYNIFG5SBRCVS5PCUY4YY2UM2WAQ4R", "length": "689", "offset": "3499", "filename": "crawl-data/CC-MAIN-2024-30/segments/1720763517846.73/crawldiagnostics/CC-MAIN-20240722095039-20240722125039-00443.warc.gz", "charset": "UTF-8", "redirect": "https://157.245.55.71/", "truncated": "length", "encoding": "ISO-8859-1"}"#;
+        let metadata = parse_cdx_line(line, 1).unwrap().metadata;
+
+        assert_eq!(metadata.status, 301);
+        assert_eq!(metadata.mime.as_deref(), Some("text/html"));
+        assert_eq!(metadata.mime_detected.as_deref(), Some("application/xhtml+xml"));
+        assert_eq!(metadata.digest.as_deref(), Some("DCNYNIFG5SBRCVS5PCUY4YY2UM2WAQ4R"));
+        assert_eq!(metadata.charset.as_deref(), Some("UTF-8"));
+        assert_eq!(metadata.redirect.as_deref(), Some("https://157.245.55.71/"));
+        assert_eq!(metadata.truncated.as_deref(), Some("length"));
+        assert_eq!(metadata.languages, None);
+        assert_eq!(metadata.extra.get("encoding").and_then(|v| v.as_str()), Some("ISO-8859-1"));
+    }
+
+    #[test]
+    fn cdx_metadata_survives_serialization_round_trip() {
+        let line = r#"0,100,59,139)/ 20240723213521 {"url": "https://139.59.100.0/", "mime": "text/html", "mime-detected": "text/html", "status": "200", "digest": "5JOQMMSNM6N7UCLGGYXDSPSB3FYAQS2C", "length": "16650", "offset": "64016172", "filename": "crawl-data/CC-MAIN-2024-30/segments/1720763518115.82/warc/CC-MAIN-20240723194208-20240723224208-00279.warc.gz", "charset": "UTF-8", "languages": "ind,eng"}"#;
+        let entry = parse_cdx_line(line, 1).unwrap();
+        let serialized = serde_json::to_string(&entry).unwrap();
+        let deserialized: CdxEntry = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(deserialized.metadata.offset, 64016172);
+        assert_eq!(deserialized.metadata.mime_detected.as_deref(), Some("text/html"));
+        assert_eq!(deserialized.metadata.languages.as_deref(), Some("ind,eng"));
+        assert!(deserialized.metadata.extra.is_empty());
+    }
+
     #[test]
     fn can_parse_cluster_idx_file_with_four_lines() {
         let content = r#"0,100,22,165)/ 20240722120756   cdx-00000.gz    0       188224  1
 101,141,199,66)/robots.txt 20240714155331       cdx-00000.gz    188224  178351  2
 104,223,1,100)/ 20240714230020  cdx-00000.gz    366575  178055  3
 107,128,254,23)/sites.asp?domain=hydrogenheaters.com 20240725183414     cdx-00000.gz    544630  181599  4"#;
-        let cdx_parts: Vec<_> = content.lines().map(parse_cluster_idx).collect();
+        let cdx_parts: Vec<_> = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| parse_cluster_idx(line, i + 1))
+            .collect::<Result<_, _>>()
+            .unwrap();
         assert_eq!(cdx_parts.len(), 4);
     }
+
+    #[test]
+    fn cdx_line_with_missing_metadata_is_an_error() {
+        let result = parse_cdx_line("0,100,22,165)/ 20240722120756", 7);
+        assert!(matches!(
+            result,
+            Err(IndexParseError::MissingField { line: 7, field: "metadata" })
+        ));
+    }
+
+    #[test]
+    fn cdx_line_with_broken_json_is_an_error() {
+        let result = parse_cdx_line(r#"0,100,22,165)/ 20240722120756 {"url": "http://165.22.100.0/""#, 3);
+        let err = result.err().unwrap();
+        assert!(matches!(err, IndexParseError::InvalidJson { .. }));
+        assert_eq!(err.line(), 3);
+    }
+
+    #[test]
+    fn cluster_idx_line_with_bad_integer_is_an_error() {
+        let result = parse_cluster_idx("0,100,22,165)/ 20240722120756 cdx-00000.gz zero 188224 1", 2);
+        assert!(matches!(
+            result,
+            Err(IndexParseError::InvalidInteger { line: 2, field: "cdx_offset", .. })
+        ));
+    }
+
+    #[test]
+    fn cluster_idx_line_with_missing_fields_is_an_error() {
+        let result = parse_cluster_idx("0,100,22,165)/ 20240722120756 cdx-00000.gz", 1);
+        assert!(matches!(
+            result,
+            Err(IndexParseError::MissingField { field: "cdx_offset", .. })
+        ));
+    }
+
+    #[test]
+    fn parse_error_policy_skips_or_fails() {
+        let broken = || parse_cluster_idx("garbage", 1);
+
+        assert!(ParseErrorPolicy::Skip.apply(broken(), "cluster.idx").unwrap().is_none());
+        assert!(ParseErrorPolicy::Fail.apply(broken(), "cluster.idx").is_err());
+    }
 }
@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod cdx_filter_tests {
+    use pipeline::cdx_filter::{CdxFilter, LanguageMatch};
+    use pipeline::commoncrawl::parse_cdx_line;
+    use regex::Regex;
+
+    const ENGLISH_OK: &str = r#"0,100,59,139)/ 20240723213521 {"url": "https://139.59.100.0/", "mime": "text/html", "mime-detected": "text/html", "status": "200", "digest": "5JOQMMSNM6N7UCLGGYXDSPSB3FYAQS2C", "length": "16650", "offset": "64016172", "filename": "crawl-data/CC-MAIN-2024-30/segments/1720763518115.82/warc/CC-MAIN-20240723194208-20240723224208-00279.warc.gz", "charset": "UTF-8", "languages": "ind,eng"}"#;
+    const REDIRECT: &str = r#"0,100,22,165)/ 20240722120756 {"url": "http://165.22.100.0/", "mime": "text/html", "mime-detected": "text/html", "status": "301", "digest": "DCNYNIFG5SBRCVS5PCUY4YY2UM2WAQ4R", "length": "689", "offset": "3499", "filename": "crawl-data/CC-MAIN-2024-30/segments/1720763517846.73/crawldiagnostics/CC-MAIN-20240722095039-20240722125039-00443.warc.gz", "redirect": "https://157.245.55.71/"}"#;
+
+    #[test]
+    fn english_and_ok_filter_keeps_english_pages_that_returned_200() {
+        let filter = CdxFilter::All {
+            filters: vec![
+                CdxFilter::Status { codes: vec![200] },
+                CdxFilter::Language {
+                    languages: vec!["eng".to_string()],
+                    mode: LanguageMatch::Any,
+                },
+            ],
+        };
+        assert!(filter.matches(&parse_cdx_line(ENGLISH_OK, 1).unwrap().metadata));
+        assert!(!filter.matches(&parse_cdx_line(REDIRECT, 1).unwrap().metadata));
+    }
+
+    #[test]
+    fn language_filter_distinguishes_primary_and_any() {
+        let metadata = parse_cdx_line(ENGLISH_OK, 1).unwrap().metadata;
+        let languages = vec!["eng".to_string()];
+
+        let any = CdxFilter::Language { languages: languages.clone(), mode: LanguageMatch::Any };
+        let primary = CdxFilter::Language { languages, mode: LanguageMatch::Primary };
+
+        assert!(any.matches(&metadata));
+        assert!(!primary.matches(&metadata));
+    }
+
+    #[test]
+    fn language_filter_rejects_entries_without_languages() {
+        let filter = CdxFilter::Language { languages: vec!["eng".to_string()], mode: LanguageMatch::Any };
+        assert!(!filter.matches(&parse_cdx_line(REDIRECT, 1).unwrap().metadata));
+    }
+
+    #[test]
+    fn status_mime_url_and_length_filters() {
+        let metadata = parse_cdx_line(REDIRECT, 1).unwrap().metadata;
+
+        assert!(CdxFilter::Status { codes: vec![200, 301] }.matches(&metadata));
+        assert!(CdxFilter::Mime { mimes: vec!["TEXT/HTML".to_string()] }.matches(&metadata));
+        assert!(!CdxFilter::Mime { mimes: vec!["application/pdf".to_string()] }.matches(&metadata));
+        assert!(CdxFilter::UrlRegex { pattern: Regex::new(r"^http://165\.").unwrap() }.matches(&metadata));
+        assert!(CdxFilter::Length { min: Some(500), max: None }.matches(&metadata));
+        assert!(!CdxFilter::Length { min: None, max: Some(500) }.matches(&metadata));
+    }
+
+    #[test]
+    fn combinators_compose_filters() {
+        let metadata = parse_cdx_line(REDIRECT, 1).unwrap().metadata;
+        let ok = CdxFilter::Status { codes: vec![200] };
+        let redirect = CdxFilter::Status { codes: vec![301] };
+
+        assert!(!CdxFilter::All { filters: vec![ok.clone(), redirect.clone()] }.matches(&metadata));
+        assert!(CdxFilter::Any { filters: vec![ok.clone(), redirect] }.matches(&metadata));
+        assert!(CdxFilter::Not { filter: Box::new(ok) }.matches(&metadata));
+    }
+
+    #[test]
+    fn can_deserialize_filter_expression_from_json() {
+        let json = r#"{
+            "type": "all",
+            "filters": [
+                { "type": "status", "codes": [200, 304] },
+                { "type": "language", "languages": ["eng"] },
+                { "type": "not", "filter": { "type": "url_regex", "pattern": "robots\\.txt$" } }
+            ]
+        }"#;
+        let filter: CdxFilter = serde_json::from_str(json).unwrap();
+
+        assert!(filter.matches(&parse_cdx_line(ENGLISH_OK, 1).unwrap().metadata));
+        assert!(!filter.matches(&parse_cdx_line(REDIRECT, 1).unwrap().metadata));
+    }
+
+    #[test]
+    fn invalid_regex_in_json_is_rejected() {
+        let json = r#"{ "type": "url_regex", "pattern": "(" }"#;
+        assert!(serde_json::from_str::<CdxFilter>(json).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod checkpoint_tests {
+    use pipeline::cdx_filter::CdxFilter;
+    use pipeline::checkpoint::BatcherCheckpoint;
+    use pipeline::surt::SurtRange;
+    use tempfile::tempdir;
+
+    #[test]
+    fn missing_checkpoint_loads_as_none() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+
+        let result = BatcherCheckpoint::load(path.to_str().unwrap()).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn stored_checkpoint_can_be_loaded() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state").join("checkpoint.json");
+        let path_str = path.to_str().unwrap();
+
+        let checkpoint = BatcherCheckpoint {
+            crawl: "CC-MAIN-2024-30".to_string(),
+            cdx_filename: "cdx-00000.gz".to_string(),
+            cluster_id: 400,
+            batches_published: 1234,
+            settings: "ABC".to_string(),
+        };
+        checkpoint.store(path_str).unwrap();
+
+        assert_eq!(BatcherCheckpoint::load(path_str).unwrap(), Some(checkpoint));
+        assert!(!dir.path().join("state").join("checkpoint.json.tmp").exists());
+    }
+
+    #[test]
+    fn newer_checkpoint_replaces_older_one() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        let path_str = path.to_str().unwrap();
+
+        let mut checkpoint = BatcherCheckpoint {
+            crawl: "CC-MAIN-2024-30".to_string(),
+            cdx_filename: "cdx-00000.gz".to_string(),
+            cluster_id: 1,
+            batches_published: 3,
+            settings: "ABC".to_string(),
+        };
+        checkpoint.store(path_str).unwrap();
+        checkpoint.cluster_id = 2;
+        checkpoint.batches_published = 5;
+        checkpoint.store(path_str).unwrap();
+
+        let loaded = BatcherCheckpoint::load(path_str).unwrap().unwrap();
+        assert_eq!(loaded.cluster_id, 2);
+        assert_eq!(loaded.batches_published, 5);
+    }
+
+    #[test]
+    fn corrupt_checkpoint_is_an_error() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        std::fs::write(&path, "{").unwrap();
+
+        assert!(BatcherCheckpoint::load(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn checkpoint_without_settings_loads_with_empty_settings() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("checkpoint.json");
+        std::fs::write(
+            &path,
+            r#"{"crawl": "CC-MAIN-2024-30", "cdx_filename": "cdx-00000.gz", "cluster_id": 1, "batches_published": 3}"#,
+        )
+        .unwrap();
+
+        let loaded = BatcherCheckpoint::load(path.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(loaded.settings, "");
+    }
+
+    #[test]
+    fn settings_hash_changes_with_the_selection() {
+        let filter = CdxFilter::Status { codes: vec![200] };
+        let ranges = vec![SurtRange::prefix("de,")];
+        let hash = BatcherCheckpoint::settings_hash("cluster.idx", &filter, &ranges).unwrap();
+
+        assert_eq!(BatcherCheckpoint::settings_hash("cluster.idx", &filter, &ranges).unwrap(), hash);
+        assert_ne!(BatcherCheckpoint::settings_hash("other.idx", &filter, &ranges).unwrap(), hash);
+        assert_ne!(
+            BatcherCheckpoint::settings_hash("cluster.idx", &CdxFilter::Status { codes: vec![404] }, &ranges).unwrap(),
+            hash
+        );
+        assert_ne!(BatcherCheckpoint::settings_hash("cluster.idx", &filter, &[]).unwrap(), hash);
+    }
+}
@@ -0,0 +1,33 @@
+#[cfg(test)]
+mod cli_tests {
+    use std::process::Command;
+
+    /// Runs `binary --help`, which fails if clap's debug assertions reject the arguments of the binary,
+    /// e.g. because two flattened argument groups use the same argument id.
+    fn assert_help_succeeds(binary: &str) {
+        let output = Command::new(binary).arg("--help").output().unwrap();
+
+        assert!(
+            output.status.success(),
+            "{} --help failed: {}",
+            binary,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Usage:"));
+    }
+
+    #[test]
+    fn batcher_arguments_are_valid() {
+        assert_help_succeeds(env!("CARGO_BIN_EXE_batcher"));
+    }
+
+    #[test]
+    fn worker_arguments_are_valid() {
+        assert_help_succeeds(env!("CARGO_BIN_EXE_worker"));
+    }
+
+    #[test]
+    fn saver_arguments_are_valid() {
+        assert_help_succeeds(env!("CARGO_BIN_EXE_saver"));
+    }
+}
@@ -0,0 +1,39 @@
+//! Fixtures shared by the integration tests. Every test crate only uses some of them.
+#![allow(dead_code)]
+
+use std::io::Write;
+
+use flate2::{write::GzEncoder, Compression};
+use pipeline::commoncrawl::{parse_cdx_line, CdxEntry};
+
+/// Compresses `content` the way the index files of a crawl are compressed.
+pub fn gzip(content: &str) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// A cdx entry of `https://example.com/` whose record is stored at `offset..offset + length` of `filename`.
+pub fn entry(filename: &str, offset: usize, length: usize) -> CdxEntry {
+    cdx_entry("com,example)/", "20240101000000", None, filename, offset, length)
+}
+
+/// A capture of `surt_url` at `timestamp` with the payload digest `digest`.
+pub fn capture(surt_url: &str, timestamp: &str, digest: &str) -> CdxEntry {
+    cdx_entry(surt_url, timestamp, Some(digest), "a.warc.gz", 0, 100)
+}
+
+fn cdx_entry(
+    surt_url: &str,
+    timestamp: &str,
+    digest: Option<&str>,
+    filename: &str,
+    offset: usize,
+    length: usize,
+) -> CdxEntry {
+    let digest = digest.map(|digest| format!(r#""digest": "{digest}", "#)).unwrap_or_default();
+    let line = format!(
+        r#"{surt_url} {timestamp} {{"url": "https://example.com/", "status": "200", {digest}"length": "{length}", "offset": "{offset}", "filename": "{filename}"}}"#
+    );
+    parse_cdx_line(&line, 1).unwrap()
+}
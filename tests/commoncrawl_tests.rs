@@ -1,9 +1,17 @@
+mod common;
+
 #[cfg(test)]
 mod commoncrawl_tests {
     use std::{fs};
+    use futures_util::TryStreamExt;
     use mockito::{Server};
     use tempfile::tempdir;
-    use pipeline::commoncrawl::download_and_store;
+    use pipeline::http_client::HttpClient;
+    use crate::common::gzip;
+    use pipeline::commoncrawl::{
+        download_and_store, download_and_unzip_lines, load_collinfo, parse_cdx_lines, CdxFileContext, CrawlInfo,
+        CrawlSelector, ParseErrorPolicy,
+    };
 
     #[tokio::test]
     async fn test_download_and_store_success() {
@@ -24,7 +32,7 @@ mod commoncrawl_tests {
         let server_url = server.url();
         let url = format!("{server_url}/index");
         
-        let result = download_and_store(&url, path_str).await;
+        let result = download_and_store(&HttpClient::default(), &url, path_str).await;
         
         assert!(result.is_ok());
         mock.assert_async().await;
@@ -44,9 +52,141 @@ mod commoncrawl_tests {
          let server_url = server.url();
          let url = format!("{server_url}/wrong-index");
 
-         let result = download_and_store(&url, "").await;
+         let result = download_and_store(&HttpClient::default(), &url, "").await;
 
          assert!(result.is_err());
          mock.expect(0).assert_async().await;
      }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_download_and_unzip_lines_streams_cdx_entries() {
+        let content = r#"0,100,22,165)/ 20240722120756 {"url": "http://165.22.100.0/", "status": "301", "length": "689", "offset": "3499", "filename": "a.warc.gz"}
+this line is broken
+0,100,59,139)/ 20240723213521 {"url": "https://139.59.100.0/", "status": "200", "length": "16650", "offset": "64016172", "filename": "b.warc.gz", "languages": "eng"}
+"#;
+        let body = gzip(content);
+        let length = body.len();
+
+        let mut server = Server::new_async().await;
+        let mock = server.mock("GET", "/cdx-00000.gz")
+            .match_header("range", format!("bytes=0-{}", length - 1).as_str())
+            .with_status(206)
+            .with_body(body)
+            .create_async()
+            .await;
+        let url = format!("{}/cdx-00000.gz", server.url());
+
+        let lines = download_and_unzip_lines(&HttpClient::default(), &url, 0, length).await.unwrap();
+        let entries: Vec<_> = parse_cdx_lines(lines, ParseErrorPolicy::Skip, url.clone())
+            .try_collect()
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].metadata.filename, "b.warc.gz");
+    }
+
+    #[tokio::test]
+    async fn test_parse_cdx_lines_fails_on_broken_line_with_fail_policy() {
+        let mut server = Server::new_async().await;
+        let body = gzip("this line is broken\n");
+        let length = body.len();
+        server.mock("GET", "/cdx-00000.gz")
+            .with_status(206)
+            .with_body(body)
+            .create_async()
+            .await;
+        let url = format!("{}/cdx-00000.gz", server.url());
+
+        let lines = download_and_unzip_lines(&HttpClient::default(), &url, 0, length).await.unwrap();
+        let result: anyhow::Result<Vec<_>> = parse_cdx_lines(lines, ParseErrorPolicy::Fail, url.clone())
+            .try_collect()
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_and_unzip_lines_requires_partial_content() {
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/cdx-00000.gz")
+            .with_status(200)
+            .create_async()
+            .await;
+        let url = format!("{}/cdx-00000.gz", server.url());
+
+        assert!(download_and_unzip_lines(&HttpClient::default(), &url, 0, 10).await.is_err());
+    }
+
+    const COLLINFO: &str = r#"[
+        {"id": "CC-MAIN-2024-33", "name": "August 2024 Index", "timegate": "https://index.commoncrawl.org/CC-MAIN-2024-33/", "cdx-api": "https://index.commoncrawl.org/CC-MAIN-2024-33-index", "from": "2024-08-02T23:27:03", "to": "2024-08-16T07:20:55"},
+        {"id": "CC-MAIN-2024-30", "name": "July 2024 Index", "timegate": "https://index.commoncrawl.org/CC-MAIN-2024-30/", "cdx-api": "https://index.commoncrawl.org/CC-MAIN-2024-30-index", "from": "2024-07-12T01:13:38", "to": "2024-07-25T22:47:24"},
+        {"id": "CC-MAIN-2023-50", "name": "November/December 2023 Index", "timegate": "https://index.commoncrawl.org/CC-MAIN-2023-50/", "cdx-api": "https://index.commoncrawl.org/CC-MAIN-2023-50-index", "from": "2023-11-28T08:46:15", "to": "2023-12-12T03:38:42"}
+    ]"#;
+
+    fn ids(crawls: Vec<&CrawlInfo>) -> Vec<&str> {
+        crawls.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_load_collinfo_from_file_and_url() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("collinfo.json");
+        fs::write(&path, COLLINFO).unwrap();
+
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/collinfo.json")
+            .with_status(200)
+            .with_body(COLLINFO)
+            .create_async()
+            .await;
+
+        let from_file = load_collinfo(&HttpClient::default(), path.to_str().unwrap()).await.unwrap();
+        let from_url = load_collinfo(&HttpClient::default(), &format!("{}/collinfo.json", server.url())).await.unwrap();
+
+        assert_eq!(from_file.len(), 3);
+        assert_eq!(from_file, from_url);
+        assert_eq!(from_file[1].from, "2024-07-12T01:13:38");
+    }
+
+    #[test]
+    fn test_crawl_selectors() {
+        let crawls: Vec<CrawlInfo> = serde_json::from_str(COLLINFO).unwrap();
+
+        let latest: CrawlSelector = "latest".parse().unwrap();
+        let year: CrawlSelector = "2024".parse().unwrap();
+        let id: CrawlSelector = "CC-MAIN-2023-50".parse().unwrap();
+
+        assert_eq!(ids(latest.select(&crawls).unwrap()), vec!["CC-MAIN-2024-33"]);
+        assert_eq!(ids(year.select(&crawls).unwrap()), vec!["CC-MAIN-2024-33", "CC-MAIN-2024-30"]);
+        assert_eq!(ids(id.select(&crawls).unwrap()), vec!["CC-MAIN-2023-50"]);
+    }
+
+    #[test]
+    fn test_crawl_selector_rejects_unknown_crawls() {
+        let crawls: Vec<CrawlInfo> = serde_json::from_str(COLLINFO).unwrap();
+
+        let typo: CrawlSelector = "CC-MAIN-2024-31".parse().unwrap();
+        assert!(typo.select(&crawls).is_err());
+        assert!("CC-MAN-2024-30".parse::<CrawlSelector>().is_err());
+    }
+
+    #[test]
+    fn test_file_context_metadata_is_optional() {
+        let without: CdxFileContext = serde_json::from_str(
+            r#"{"filename": "a.warc.gz", "content": "text", "target_uri": "https://example.com/", "tokens": []}"#,
+        )
+        .unwrap();
+        assert_eq!(without.metadata.date, None);
+
+        let with: CdxFileContext = serde_json::from_str(
+            r#"{"filename": "a.warc.gz", "content": "text", "target_uri": "https://example.com/", "tokens": [],
+                "metadata": {"title": "Title", "date": "2024-07-01", "sitename": "Example", "categories": ["news"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(with.metadata.date.as_deref(), Some("2024-07-01"));
+        assert_eq!(with.metadata.sitename.as_deref(), Some("Example"));
+        assert_eq!(with.metadata.categories, vec!["news".to_string()]);
+    }
+}
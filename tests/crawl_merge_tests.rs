@@ -0,0 +1,96 @@
+mod common;
+
+#[cfg(test)]
+mod crawl_merge_tests {
+    use futures_util::{stream, TryStreamExt};
+    use pipeline::commoncrawl::CdxEntry;
+    use pipeline::crawl_merge::{merge_sorted, DedupKey, NewestCaptureDeduplicator};
+
+    use crate::common::capture;
+
+    fn dedup(key: DedupKey, entries: Vec<(usize, CdxEntry)>) -> (Vec<CdxEntry>, Vec<usize>) {
+        let mut deduplicator = NewestCaptureDeduplicator::new(key, vec!["old".to_string(), "new".to_string()]);
+        let mut survivors = Vec::new();
+        for (source, entry) in entries {
+            survivors.extend(deduplicator.push(source, entry));
+        }
+        survivors.extend(deduplicator.finish());
+        (survivors, deduplicator.duplicates().to_vec())
+    }
+
+    #[tokio::test]
+    async fn merge_sorted_interleaves_streams_in_surt_order() {
+        let first = stream::iter(vec![
+            Ok(capture("com,a)/", "20240101000000", "A")),
+            Ok(capture("com,c)/", "20240101000000", "C")),
+        ]);
+        let second = stream::iter(vec![
+            Ok(capture("com,a)/", "20240601000000", "A")),
+            Ok(capture("com,b)/", "20240601000000", "B")),
+        ]);
+
+        let merged: Vec<_> = merge_sorted(vec![first, second]).try_collect().await.unwrap();
+        let order: Vec<_> = merged
+            .iter()
+            .map(|(source, e)| (*source, e.surt_url.as_str()))
+            .collect();
+
+        assert_eq!(order, vec![(0, "com,a)/"), (1, "com,a)/"), (1, "com,b)/"), (0, "com,c)/")]);
+    }
+
+    #[tokio::test]
+    async fn merge_sorted_stops_at_first_error() {
+        let first = stream::iter(vec![Err(anyhow::anyhow!("broken"))]);
+        let second = stream::iter(vec![Ok(capture("com,a)/", "20240601000000", "A"))]);
+
+        let result: anyhow::Result<Vec<_>> = merge_sorted(vec![first, second]).try_collect().await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn deduplicator_keeps_newest_capture_per_surt() {
+        let (survivors, duplicates) = dedup(
+            DedupKey::Surt,
+            vec![
+                (0, capture("com,a)/", "20240101000000", "A")),
+                (1, capture("com,a)/", "20240601000000", "B")),
+                (1, capture("com,b)/", "20240601000000", "B")),
+            ],
+        );
+
+        let kept: Vec<_> = survivors.iter().map(|e| (e.surt_url.as_str(), e.timestamp.as_str())).collect();
+        assert_eq!(kept, vec![("com,a)/", "20240601000000"), ("com,b)/", "20240601000000")]);
+        assert_eq!(duplicates, vec![1, 0]);
+    }
+
+    #[test]
+    fn deduplicator_counts_older_capture_from_newer_crawl() {
+        let (survivors, duplicates) = dedup(
+            DedupKey::Surt,
+            vec![
+                (1, capture("com,a)/", "20230101000000", "A")),
+                (0, capture("com,a)/", "20240101000000", "A")),
+            ],
+        );
+
+        assert_eq!(survivors.len(), 1);
+        assert_eq!(survivors[0].timestamp, "20240101000000");
+        assert_eq!(duplicates, vec![0, 1]);
+    }
+
+    #[test]
+    fn deduplicator_with_digest_key_keeps_changed_content() {
+        let (survivors, duplicates) = dedup(
+            DedupKey::SurtDigest,
+            vec![
+                (0, capture("com,a)/", "20240101000000", "A")),
+                (1, capture("com,a)/", "20240601000000", "A")),
+                (1, capture("com,a)/", "20240602000000", "B")),
+            ],
+        );
+
+        let kept: Vec<_> = survivors.iter().map(|e| e.timestamp.as_str()).collect();
+        assert_eq!(kept, vec!["20240601000000", "20240602000000"]);
+        assert_eq!(duplicates, vec![1, 0]);
+    }
+}
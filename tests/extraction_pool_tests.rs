@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod extraction_pool_tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures_util::future::join_all;
+    use pipeline::extraction_pool::ExtractionPool;
+
+    #[tokio::test]
+    async fn run_returns_job_result() {
+        let pool = ExtractionPool::new(1);
+
+        assert_eq!(pool.run(|| Ok(42)).await.unwrap(), 42);
+        assert!(pool.run(|| -> anyhow::Result<()> { anyhow::bail!("failed") }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn panicking_job_is_an_error() {
+        let pool = ExtractionPool::new(1);
+
+        let result = pool.run(|| -> anyhow::Result<()> { panic!("boom") }).await;
+
+        assert!(result.is_err());
+        // the pool is still usable afterwards
+        assert_eq!(pool.run(|| Ok(1)).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn at_most_size_jobs_run_at_once() {
+        let pool = ExtractionPool::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let jobs = (0..8).map(|_| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            pool.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            })
+        });
+        for result in join_all(jobs).await {
+            result.unwrap();
+        }
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+}
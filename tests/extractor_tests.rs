@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod extractor_tests {
+    use clap::Parser;
+    use dom_smoothie::TextMode;
+    use pipeline::extractor::{
+        ExtractedDocument, ExtractionFocus, ExtractionOptions, ExtractorArgs, ExtractorBackend, OutputFormat,
+    };
+    use pipeline::python_processes::ProcessCommand;
+    use pipeline::readability;
+    use serde_json::json;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        extractor: ExtractorArgs,
+    }
+
+    const ARTICLE: &str = r#"<html><head><title>A story</title>
+        <meta name="author" content="Jane Doe">
+        <meta property="og:site_name" content="Example News">
+        <meta property="article:published_time" content="2024-07-01T10:00:00Z">
+    </head><body>
+        <nav><a href="/">Home</a> <a href="/about">About</a></nav>
+        <article>
+            <h1>A story</h1>
+            <p>Once upon a time there was a pipeline that downloaded a large part of the web, one WARC record
+            after another, and turned every page into plain text for the training of language models.</p>
+            <p>It had to separate the interesting parts of each page from menus, footers and advertisements,
+            which is harder than it sounds, because every site is built in a slightly different way.</p>
+            <p>Luckily, the main content of a page usually consists of long paragraphs with few links,
+            while boilerplate consists of short snippets full of links, and that is easy to score.</p>
+        </article>
+        <footer>Copyright <a href="/imprint">Imprint</a></footer>
+    </body></html>"#;
+
+    const FORMATTED_ARTICLE: &str = r#"<html><head><title>A list</title></head><body>
+        <article>
+            <h1>A list</h1>
+            <p>A pipeline that turns web pages into text has to do a few things, and <b>all of them</b> have to
+            work at the scale of a whole crawl of the web, with billions of pages and thousands of sites:</p>
+            <ul>
+                <li>download the WARC records of the selected pages from the crawl</li>
+                <li>extract the main content of each page, leaving out the boilerplate</li>
+                <li>store the text together with the metadata of the page</li>
+            </ul>
+        </article>
+    </body></html>"#;
+
+    /// Whether `python3` can import trafilatura. The tests of the trafilatura backends are skipped otherwise.
+    fn trafilatura_installed() -> bool {
+        let installed = std::process::Command::new("python3")
+            .args(["-c", "import trafilatura"])
+            .status()
+            .is_ok_and(|status| status.success());
+        if !installed {
+            eprintln!("trafilatura is not installed, skipping");
+        }
+        installed
+    }
+
+    /// Extracts `html` with real trafilatura in a child process, passing `options` on the command line.
+    async fn trafilatura_processes(html: &str, options: &[&str]) -> ExtractedDocument {
+        let args = ["test", "--extraction-backend", "trafilatura-processes", "--python-processes", "1"];
+        let args = TestArgs::parse_from(args.iter().chain(options));
+        args.extractor.build().unwrap().extract(html.to_string()).await.unwrap().unwrap()
+    }
+
+    #[test]
+    fn readability_extracts_main_content() {
+        let document = readability::extract(ARTICLE, TextMode::Formatted).unwrap().unwrap();
+        let text = document.text;
+
+        assert!(text.contains("Once upon a time there was a pipeline"));
+        assert!(text.contains("that is easy to score."));
+        assert!(!text.contains("Imprint"));
+    }
+
+    #[test]
+    fn readability_extracts_metadata() {
+        let metadata = readability::extract(ARTICLE, TextMode::Formatted).unwrap().unwrap().metadata;
+
+        assert_eq!(metadata.title.as_deref(), Some("A story"));
+        assert_eq!(metadata.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(metadata.sitename.as_deref(), Some("Example News"));
+        assert_eq!(metadata.date.as_deref(), Some("2024-07-01T10:00:00Z"));
+    }
+
+    #[tokio::test]
+    async fn trafilatura_extracts_metadata() {
+        if !trafilatura_installed() {
+            return;
+        }
+        let metadata = trafilatura_processes(ARTICLE, &[]).await.metadata;
+
+        assert_eq!(metadata.title.as_deref(), Some("A story"));
+        assert_eq!(metadata.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(metadata.sitename.as_deref(), Some("Example News"));
+        assert_eq!(metadata.date.as_deref(), Some("2024-07-01"));
+    }
+
+    #[tokio::test]
+    async fn trafilatura_renders_markdown() {
+        if !trafilatura_installed() {
+            return;
+        }
+        let markdown = trafilatura_processes(FORMATTED_ARTICLE, &["--output-format", "markdown"]).await.text;
+        let plain = trafilatura_processes(FORMATTED_ARTICLE, &[]).await.text;
+
+        assert!(markdown.contains("**all of them**"), "{markdown}");
+        assert!(markdown.contains("- download the WARC records"), "{markdown}");
+        assert!(!plain.contains("**"), "{plain}");
+    }
+
+    #[test]
+    fn readability_returns_none_without_content() {
+        assert!(readability::extract("<html><body></body></html>", TextMode::Formatted).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn readability_backend_is_selectable() {
+        let args = TestArgs::parse_from(["test", "--extraction-backend", "readability"]);
+        assert_eq!(args.extractor.backend, ExtractorBackend::Readability);
+
+        let extractor = args.extractor.build().unwrap();
+        let text = extractor.extract(ARTICLE.to_string()).await.unwrap().unwrap().text;
+
+        assert!(text.contains("Once upon a time there was a pipeline"));
+    }
+
+    #[test]
+    fn trafilatura_is_the_default_backend() {
+        let args = TestArgs::parse_from(["test"]);
+
+        assert_eq!(args.extractor.backend, ExtractorBackend::Trafilatura);
+    }
+
+    #[test]
+    fn previous_backend_names_are_still_accepted() {
+        let args = TestArgs::parse_from(["worker", "--extraction-backend", "in-process"]);
+        assert_eq!(args.extractor.backend, ExtractorBackend::Trafilatura);
+        let args = TestArgs::parse_from(["worker", "--extraction-backend", "processes"]);
+        assert_eq!(args.extractor.backend, ExtractorBackend::TrafilaturaProcesses);
+    }
+
+    #[test]
+    fn default_options_match_trafilatura_keyword_arguments() {
+        let args = TestArgs::parse_from(["test"]);
+        assert_eq!(args.extractor.options, ExtractionOptions::default());
+
+        assert_eq!(
+            serde_json::to_value(&args.extractor.options).unwrap(),
+            json!({
+                "include_comments": false,
+                "include_tables": false,
+                "deduplicate": true,
+                "include_links": false,
+                "include_formatting": false,
+                "output_format": "txt",
+                "favor_precision": false,
+                "favor_recall": false,
+            })
+        );
+    }
+
+    #[test]
+    fn options_are_configurable() {
+        let args = TestArgs::parse_from([
+            "test",
+            "--include-tables",
+            "--no-deduplicate",
+            "--include-links",
+            "--output-format",
+            "markdown",
+            "--extraction-focus",
+            "precision",
+        ]);
+        let options = &args.extractor.options;
+
+        assert!(options.include_tables && options.include_links && !options.deduplicate);
+        assert_eq!(options.output_format, OutputFormat::Markdown);
+        assert_eq!(options.focus, ExtractionFocus::Precision);
+        let value = serde_json::to_value(options).unwrap();
+        assert_eq!(value["favor_precision"], json!(true));
+        assert_eq!(value["output_format"], json!("markdown"));
+    }
+
+    #[test]
+    fn options_are_passed_to_extraction_processes() {
+        let options = ExtractionOptions {
+            include_comments: true,
+            ..ExtractionOptions::default()
+        };
+
+        let command = ProcessCommand::trafilatura("python3", &options).unwrap();
+
+        let passed: serde_json::Value = serde_json::from_str(command.args.last().unwrap()).unwrap();
+        assert_eq!(passed["include_comments"], json!(true));
+    }
+
+    #[tokio::test]
+    async fn readability_supports_markdown_but_not_xml() {
+        let args = TestArgs::parse_from(["test", "--extraction-backend", "readability", "--output-format", "markdown"]);
+        let text = args.extractor.build().unwrap().extract(ARTICLE.to_string()).await.unwrap().unwrap().text;
+        assert!(text.contains("Once upon a time there was a pipeline"));
+
+        let args = TestArgs::parse_from(["test", "--extraction-backend", "readability", "--output-format", "xml"]);
+        assert!(args.extractor.build().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod http_client_tests {
+    use clap::Parser;
+    use mockito::Server;
+    use pipeline::commoncrawl::download_and_store;
+    use tempfile::tempdir;
+    use pipeline::http_client::{HttpClient, HttpClientArgs, DEFAULT_USER_AGENT};
+    use pipeline::rate_limit::RateLimiter;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        http: HttpClientArgs,
+    }
+
+    #[tokio::test]
+    async fn client_sends_configured_user_agent() {
+        let mut server = Server::new_async().await;
+        let mock = server.mock("GET", "/cluster.idx")
+            .match_header("user-agent", "my-pipeline/1.0")
+            .with_status(200)
+            .with_body("index content")
+            .create_async()
+            .await;
+
+        let args = TestArgs::parse_from(["test", "--http-user-agent", "my-pipeline/1.0"]);
+        let client = HttpClient::new(args.http.build().unwrap(), RateLimiter::unlimited());
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cluster.idx");
+        let url = format!("{}/cluster.idx", server.url());
+        download_and_store(&client, &url, path.to_str().unwrap()).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn defaults_identify_the_pipeline() {
+        let args = TestArgs::parse_from(["test"]);
+
+        assert_eq!(args.http.user_agent, DEFAULT_USER_AGENT);
+        assert!(args.http.proxy.is_none());
+        assert!(args.http.build().is_ok());
+    }
+
+    #[test]
+    fn invalid_proxy_is_rejected() {
+        let args = TestArgs::parse_from(["test", "--http-proxy", "not a url"]);
+
+        assert!(args.http.build().is_err());
+    }
+}
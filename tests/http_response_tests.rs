@@ -0,0 +1,104 @@
+mod common;
+
+#[cfg(test)]
+mod http_response_tests {
+    use std::io::Write;
+
+    use pipeline::http_response::{HttpParseError, HttpResponse};
+
+    use crate::common::gzip;
+
+    fn response(headers: &str, body: &[u8]) -> HttpResponse {
+        let mut data = format!("HTTP/1.1 200 OK\r\n{}\r\n", headers).into_bytes();
+        data.extend_from_slice(body);
+        HttpResponse::parse(&data).unwrap()
+    }
+
+    #[test]
+    fn parses_status_headers_and_body() {
+        let response = response("Content-Type: text/html; charset=\"UTF-8\"\r\nServer: test\r\n", b"<p>hi</p>");
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("text/html; charset=\"UTF-8\""));
+        assert_eq!(response.mime_type().as_deref(), Some("text/html"));
+        assert_eq!(response.charset(), Some("UTF-8"));
+        assert_eq!(response.body, b"<p>hi</p>");
+    }
+
+    #[test]
+    fn accepts_bare_newlines() {
+        let response = HttpResponse::parse(b"HTTP/1.1 404 Not Found\nContent-Type: text/html\n\n<p>gone</p>").unwrap();
+
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, b"<p>gone</p>");
+    }
+
+    #[test]
+    fn incomplete_header_is_an_error() {
+        let result = HttpResponse::parse(b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n");
+
+        assert!(matches!(result, Err(HttpParseError::IncompleteHeader)));
+    }
+
+    #[test]
+    fn decodes_chunked_gzip_body() {
+        let gzipped = gzip("<p>compressed</p>");
+        let (first, second) = gzipped.split_at(5);
+        let mut chunked = format!("{:x}\r\n", first.len()).into_bytes();
+        chunked.extend_from_slice(first);
+        chunked.extend_from_slice(format!("\r\n{:x};ext=1\r\n", second.len()).as_bytes());
+        chunked.extend_from_slice(second);
+        chunked.extend_from_slice(b"\r\n0\r\n\r\n");
+
+        let response = response("Transfer-Encoding: chunked\r\nContent-Encoding: gzip\r\n", &chunked);
+
+        assert_eq!(response.decoded_body().unwrap(), b"<p>compressed</p>");
+    }
+
+    #[test]
+    fn decodes_brotli_body() {
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+            encoder.write_all(b"<p>brotli</p>").unwrap();
+        }
+
+        let response = response("Content-Encoding: br\r\n", &compressed);
+
+        assert_eq!(response.text(None).unwrap(), "<p>brotli</p>");
+    }
+
+    #[test]
+    fn invalid_encodings_are_errors() {
+        assert!(response("Transfer-Encoding: chunked\r\n", b"zz\r\nabc").decoded_body().is_err());
+        assert!(response("Content-Encoding: gzip\r\n", b"not gzip").decoded_body().is_err());
+        assert!(matches!(
+            response("Content-Encoding: compress\r\n", b"data").decoded_body(),
+            Err(HttpParseError::UnsupportedContentEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn charset_from_header_takes_precedence() {
+        // "é" in ISO-8859-1
+        let response = response("Content-Type: text/html; charset=iso-8859-1\r\n", b"<meta charset=\"utf-8\">caf\xe9");
+
+        assert_eq!(response.text(Some("utf-8")).unwrap(), "<meta charset=\"utf-8\">café");
+    }
+
+    #[test]
+    fn charset_from_meta_tag() {
+        let body = b"<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=windows-1251\"></head>\xcf\xf0\xe8\xe2\xe5\xf2";
+        let response = response("Content-Type: text/html\r\n", body);
+
+        assert!(response.text(None).unwrap().ends_with("Привет"));
+    }
+
+    #[test]
+    fn charset_from_cdx_index_and_utf8_fallback() {
+        let body = b"<html>\x82\xb1\x82\xf1\x82\xc9\x82\xbf\x82\xcd</html>";
+
+        assert_eq!(response("", body).text(Some("Shift_JIS")).unwrap(), "<html>こんにちは</html>");
+        assert!(response("", body).text(None).unwrap().contains('\u{FFFD}'));
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod python_processes_tests {
+    use std::time::Duration;
+
+    use pipeline::python_processes::{ProcessCommand, PythonProcessPool};
+
+    /// Speaks the protocol of `extract_text.py` without needing trafilatura:
+    /// upper-cases the document and adds a title, and crashes, hangs, fails or returns nothing on request.
+    const FAKE_EXTRACTOR: &str = r#"
+import json, os, struct, sys, time
+requests, responses = sys.stdin.buffer, sys.stdout.buffer
+while True:
+    header = requests.read(4)
+    if len(header) < 4:
+        break
+    (length,) = struct.unpack(">I", header)
+    content = requests.read(length).decode("utf-8")
+    if content == "crash":
+        os._exit(1)
+    if content == "hang":
+        time.sleep(60)
+    if content == "fail":
+        status, payload = 2, b"ValueError()"
+    elif content == "empty":
+        status, payload = 0, b""
+    else:
+        document = {"text": content.upper(), "title": "Title", "categories": ["news"]}
+        status, payload = 1, json.dumps(document).encode("utf-8")
+    responses.write(struct.pack(">BI", status, len(payload)))
+    responses.write(payload)
+    responses.flush()
+"#;
+
+    async fn text(pool: &PythonProcessPool, html: &str) -> anyhow::Result<Option<String>> {
+        Ok(pool.extract(html).await?.map(|document| document.text))
+    }
+
+    fn pool(size: usize, timeout: Duration) -> PythonProcessPool {
+        let command = ProcessCommand {
+            program: "python3".to_string(),
+            args: vec!["-c".to_string(), FAKE_EXTRACTOR.to_string()],
+        };
+        PythonProcessPool::new(command, size, timeout).unwrap()
+    }
+
+    #[tokio::test]
+    async fn extracts_documents_in_child_processes() {
+        let pool = pool(2, Duration::from_secs(10));
+
+        let document = pool.extract("<p>hello</p>").await.unwrap().unwrap();
+        assert_eq!(document.text, "<P>HELLO</P>");
+        assert_eq!(document.metadata.title.as_deref(), Some("Title"));
+        assert_eq!(document.metadata.author, None);
+        assert_eq!(document.metadata.categories, vec!["news".to_string()]);
+        assert!(pool.extract("empty").await.unwrap().is_none());
+        assert_eq!(text(&pool, "ünïcödé").await.unwrap(), Some("ÜNÏCÖDÉ".to_string()));
+    }
+
+    #[tokio::test]
+    async fn python_errors_keep_the_process() {
+        let pool = pool(1, Duration::from_secs(10));
+
+        assert!(pool.extract("fail").await.is_err());
+        assert_eq!(text(&pool, "next").await.unwrap(), Some("NEXT".to_string()));
+    }
+
+    #[tokio::test]
+    async fn crashed_process_is_restarted() {
+        let pool = pool(1, Duration::from_secs(10));
+
+        assert!(pool.extract("crash").await.is_err());
+        assert_eq!(text(&pool, "again").await.unwrap(), Some("AGAIN".to_string()));
+    }
+
+    #[tokio::test]
+    async fn slow_document_times_out_and_process_is_replaced() {
+        let pool = pool(1, Duration::from_millis(500));
+
+        assert!(pool.extract("hang").await.is_err());
+        assert_eq!(text(&pool, "fast").await.unwrap(), Some("FAST".to_string()));
+    }
+
+    #[test]
+    fn missing_interpreter_is_an_error() {
+        let command = ProcessCommand {
+            program: "/does/not/exist/python".to_string(),
+            args: vec![],
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+
+        assert!(PythonProcessPool::new(command, 1, Duration::from_secs(1)).is_err());
+    }
+}
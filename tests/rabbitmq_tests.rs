@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod rabbitmq_tests {
+    use std::time::Duration;
+
+    use lapin::types::{AMQPValue, FieldTable};
+    use lapin::BasicProperties;
+    use pipeline::rabbitmq::{
+        dead_letter_queue_name, delivery_attempt, retry_properties, retry_queue_arguments, retry_queue_name,
+        work_queue_arguments, ATTEMPT_HEADER, DEAD_LETTER_EXCHANGE,
+    };
+
+    fn argument(arguments: &FieldTable, key: &str) -> Option<AMQPValue> {
+        arguments.inner().get(key).cloned()
+    }
+
+    #[test]
+    fn work_queues_dead_letter_into_their_dead_letter_queue() {
+        let arguments = work_queue_arguments("stores");
+
+        assert_eq!(
+            argument(&arguments, "x-dead-letter-exchange"),
+            Some(AMQPValue::LongString(DEAD_LETTER_EXCHANGE.into()))
+        );
+        assert_eq!(argument(&arguments, "x-dead-letter-routing-key"), Some(AMQPValue::LongString("stores".into())));
+        assert_eq!(dead_letter_queue_name("stores"), "stores.dead");
+    }
+
+    #[test]
+    fn retry_queues_expire_into_the_work_queue() {
+        let arguments = retry_queue_arguments("stores");
+
+        assert_eq!(argument(&arguments, "x-dead-letter-exchange"), Some(AMQPValue::LongString("".into())));
+        assert_eq!(argument(&arguments, "x-dead-letter-routing-key"), Some(AMQPValue::LongString("stores".into())));
+        assert_eq!(retry_queue_name("stores"), "stores.retry");
+    }
+
+    #[test]
+    fn messages_without_header_are_on_their_first_attempt() {
+        assert_eq!(delivery_attempt(&BasicProperties::default()), 1);
+    }
+
+    #[test]
+    fn retry_properties_count_attempts_and_expire() {
+        let mut headers = FieldTable::default();
+        headers.insert("x-other".into(), AMQPValue::Boolean(true));
+        let original = BasicProperties::default().with_headers(headers);
+
+        let properties = retry_properties(&original, 3, Duration::from_secs(30));
+
+        assert_eq!(delivery_attempt(&properties), 3);
+        assert_eq!(properties.expiration().as_ref().map(|e| e.as_str()), Some("30000"));
+        let headers = properties.headers().as_ref().unwrap();
+        assert_eq!(headers.inner().get("x-other"), Some(&AMQPValue::Boolean(true)));
+        assert_eq!(headers.inner().get(ATTEMPT_HEADER), Some(&AMQPValue::LongUInt(3)));
+    }
+
+    #[test]
+    fn attempt_header_of_other_integer_types_is_read() {
+        let mut headers = FieldTable::default();
+        headers.insert(ATTEMPT_HEADER.into(), AMQPValue::LongLongInt(4));
+        assert_eq!(delivery_attempt(&BasicProperties::default().with_headers(headers)), 4);
+
+        let mut headers = FieldTable::default();
+        headers.insert(ATTEMPT_HEADER.into(), AMQPValue::LongInt(-2));
+        assert_eq!(delivery_attempt(&BasicProperties::default().with_headers(headers)), 1);
+    }
+}
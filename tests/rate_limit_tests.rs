@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod rate_limit_tests {
+    use std::time::{Duration, Instant};
+
+    use clap::Parser;
+    use pipeline::rate_limit::{RateLimitArgs, RateLimiter, TokenBucket};
+    use tempfile::tempdir;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        rate_limit: RateLimitArgs,
+    }
+
+    #[test]
+    fn token_bucket_allows_burst_then_spaces_requests() {
+        let mut bucket = TokenBucket::new(2.0, 2.0, 0.0);
+
+        assert_eq!(bucket.reserve(0.0), Duration::ZERO);
+        assert_eq!(bucket.reserve(0.0), Duration::ZERO);
+        assert_eq!(bucket.reserve(0.0), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(0.0), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn token_bucket_refills_up_to_burst() {
+        let mut bucket = TokenBucket::new(1.0, 2.0, 0.0);
+        bucket.reserve(0.0);
+        bucket.reserve(0.0);
+
+        // an hour later, the bucket is full again but not fuller than the burst
+        assert_eq!(bucket.reserve(3600.0), Duration::ZERO);
+        assert_eq!(bucket.reserve(3600.0), Duration::ZERO);
+        assert_eq!(bucket.reserve(3600.0), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn limiter_waits_for_tokens() {
+        let limiter = RateLimiter::new(20.0, 1.0);
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await.unwrap();
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn shared_limit_is_enforced_across_limiters() {
+        let dir = tempdir().unwrap();
+        let state = dir.path().join("rate_limit.state");
+        let first = RateLimiter::unlimited().with_shared(&state, 20.0, 1.0).unwrap();
+        let second = RateLimiter::unlimited().with_shared(&state, 20.0, 1.0).unwrap();
+
+        let start = Instant::now();
+        first.acquire().await.unwrap();
+        second.acquire().await.unwrap();
+        first.acquire().await.unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn limiter_is_unlimited_by_default() {
+        let args = TestArgs::parse_from(["test"]);
+        let limiter = args.rate_limit.limiter().unwrap();
+
+        let start = Instant::now();
+        for _ in 0..100 {
+            limiter.acquire().await.unwrap();
+        }
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn invalid_rates_are_rejected() {
+        let args = TestArgs::parse_from(["test", "--rate-limit", "0"]);
+        assert!(args.rate_limit.limiter().is_err());
+
+        let args = TestArgs::parse_from(["test", "--rate-limit", "1", "--rate-limit-burst", "0.5"]);
+        assert!(args.rate_limit.limiter().is_err());
+    }
+}
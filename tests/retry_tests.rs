@@ -0,0 +1,140 @@
+#[cfg(test)]
+mod retry_tests {
+    use std::time::Duration;
+    use mockito::Server;
+    use pipeline::http_client::HttpClient;
+    use pipeline::commoncrawl::{parse_retry_after, HttpStatusError};
+    use pipeline::retry::{retryable, with_retry, RetryPolicy};
+    use pipeline::source::CrawlSource;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        }
+    }
+
+    fn status_error(status: u16) -> anyhow::Error {
+        HttpStatusError {
+            url: "https://data.commoncrawl.org/".to_string(),
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            retry_after: None,
+        }
+        .into()
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_with_bounded_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+        };
+
+        for _ in 0..20 {
+            let first = policy.backoff(1);
+            let third = policy.backoff(3);
+            let tenth = policy.backoff(10);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+            assert!(tenth >= Duration::from_millis(500) && tenth <= Duration::from_millis(1000));
+        }
+    }
+
+    #[test]
+    fn only_transient_errors_are_retryable() {
+        assert!(retryable(&status_error(503)).is_some());
+        assert!(retryable(&status_error(429)).is_some());
+        assert!(retryable(&status_error(404)).is_none());
+        assert!(retryable(&anyhow::anyhow!("parse error")).is_none());
+        assert!(retryable(&status_error(500).context("while downloading")).is_some());
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_dates() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_max_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+        };
+
+        assert_eq!(policy.retry_delay(1, Some(Duration::from_secs(10))), Duration::from_secs(10));
+        assert_eq!(policy.retry_delay(1, Some(Duration::from_secs(86400))), Duration::from_secs(30));
+        assert!(policy.retry_delay(1, Some(Duration::ZERO)) >= Duration::from_millis(50));
+        assert!(policy.retry_delay(1, None) <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn unexpected_eof_of_local_data_is_not_retryable() {
+        let truncated = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated gzip member");
+        assert!(retryable(&anyhow::Error::from(truncated)).is_none());
+
+        let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset");
+        assert!(retryable(&anyhow::Error::from(reset)).is_some());
+    }
+
+    #[tokio::test]
+    async fn with_retry_retries_until_success() {
+        let mut calls = 0;
+        let result = with_retry(&fast_policy(5), "test", || {
+            calls += 1;
+            let attempt = calls;
+            async move {
+                if attempt < 3 {
+                    Err(status_error(503))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn with_retry_gives_up_after_max_attempts_and_on_permanent_errors() {
+        let mut calls = 0;
+        let result: anyhow::Result<()> = with_retry(&fast_policy(3), "test", || {
+            calls += 1;
+            async { Err(status_error(503)) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: anyhow::Result<()> = with_retry(&fast_policy(3), "test", || {
+            calls += 1;
+            async { Err(status_error(404)) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn range_download_reports_status_and_retry_after() {
+        let mut server = Server::new_async().await;
+        server.mock("GET", "/a.warc.gz")
+            .with_status(503)
+            .with_header("retry-after", "7")
+            .create_async()
+            .await;
+
+        let source = CrawlSource::parse(&server.url()).unwrap();
+        let err = source.download(&HttpClient::default(), "a.warc.gz", 0, 10).await.err().unwrap();
+
+        let status = err.downcast_ref::<HttpStatusError>().unwrap();
+        assert_eq!(status.status, reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(retryable(&err), Some(Some(Duration::from_secs(7))));
+    }
+}
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod surt_tests {
+    use pipeline::commoncrawl::{parse_cluster_idx, ClusterIdxEntry};
+    use pipeline::surt::{chunk_limit_range, in_ranges, select_chunks, SurtRange};
+
+    fn cluster_idx() -> Vec<ClusterIdxEntry> {
+        let content = r#"at,example)/ 20240722120756 cdx-00000.gz 0 100 1
+com,example)/ 20240722120756 cdx-00000.gz 100 100 2
+com,example)/page 20240722120756 cdx-00000.gz 200 100 3
+com,zeta)/ 20240722120756 cdx-00000.gz 300 100 4
+de,beispiel)/ 20240722120756 cdx-00001.gz 0 100 5
+fr,exemple)/ 20240722120756 cdx-00001.gz 100 100 6"#;
+        content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| parse_cluster_idx(line, i + 1).unwrap())
+            .collect()
+    }
+
+    fn ids(idx: Vec<ClusterIdxEntry>) -> Vec<usize> {
+        idx.iter().map(|e| e.cluster_id).collect()
+    }
+
+    #[test]
+    fn prefix_range_contains_only_matching_keys() {
+        let range = SurtRange::prefix("com,example)/");
+
+        assert!(range.contains("com,example)/"));
+        assert!(range.contains("com,example)/about"));
+        assert!(!range.contains("com,example,www)/"));
+        assert!(!range.contains("com,examplf)/"));
+        assert_eq!(range.end.as_deref(), Some("com,example)0"));
+    }
+
+    #[test]
+    fn no_ranges_select_everything() {
+        assert_eq!(select_chunks(cluster_idx(), &[]).len(), 6);
+        assert!(in_ranges(&[], "com,example)/"));
+    }
+
+    #[test]
+    fn prefix_selects_overlapping_chunks_only() {
+        let selected = select_chunks(cluster_idx(), &[SurtRange::prefix("com,example)/")]);
+        // chunk 1 may contain the first keys of the prefix, chunk 3 the last ones
+        assert_eq!(ids(selected), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn tld_prefix_within_a_single_chunk() {
+        let selected = select_chunks(cluster_idx(), &[SurtRange::prefix("de,")]);
+        assert_eq!(ids(selected), vec![4, 5]);
+    }
+
+    #[test]
+    fn open_ended_and_multiple_ranges() {
+        let from_fr = SurtRange { start: "fr,".to_string(), end: None };
+        let up_to_com = SurtRange { start: String::new(), end: Some("com,".to_string()) };
+
+        let both = vec![up_to_com, from_fr];
+
+        assert_eq!(ids(select_chunks(cluster_idx(), &both[1..])), vec![5, 6]);
+        assert_eq!(ids(select_chunks(cluster_idx(), &both)), vec![1, 5, 6]);
+        assert!(in_ranges(&both, "fr,exemple)/"));
+        assert!(!in_ranges(&both, "de,beispiel)/"));
+    }
+
+    #[test]
+    fn chunk_limit_covers_the_first_chunks() {
+        let limit = chunk_limit_range(&cluster_idx(), 3).unwrap();
+
+        assert_eq!(limit.end.as_deref(), Some("com,zeta)/"));
+        assert_eq!(ids(select_chunks(cluster_idx(), std::slice::from_ref(&limit))), vec![1, 2, 3]);
+        assert!(limit.contains("com,example)/page"));
+        assert!(!limit.contains("com,zeta)/"));
+        assert_eq!(chunk_limit_range(&cluster_idx(), 6), None);
+    }
+}
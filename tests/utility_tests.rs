@@ -1,6 +1,18 @@
 #[cfg(test)]
 mod utility_tests {
-    use pipeline::utility::{calculate_hash};
+    use pipeline::commoncrawl::CdxFileContext;
+    use pipeline::extractor::DocumentMetadata;
+    use pipeline::utility::{calculate_hash, metadata_headers, object_name};
+
+    fn document(filename: &str, target_uri: &str) -> CdxFileContext {
+        CdxFileContext {
+            filename: filename.to_string(),
+            content: "content".to_string(),
+            target_uri: target_uri.to_string(),
+            tokens: vec![],
+            metadata: Default::default(),
+        }
+    }
 
     #[test]
     fn test_calculate_hash_empty_string() {
@@ -36,4 +48,29 @@ mod utility_tests {
         let expected = "FA65D94B3532D83FD24ADA92DADECFC7AE5370E6DBF762133027A89C2E7202F1";
         assert_eq!(result, expected, "The hash for '你好，世界！' is incorrect.");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_object_name_is_stable_per_document() {
+        let first = document("a.warc.gz", "https://example.com/1");
+
+        assert_eq!(object_name(&first), object_name(&first.clone()));
+        assert_ne!(object_name(&first), object_name(&document("a.warc.gz", "https://example.com/2")));
+        assert!(object_name(&first).starts_with("a.warc.gz/"));
+        assert!(object_name(&first).ends_with(".json"));
+    }
+
+    #[test]
+    fn test_metadata_headers_are_user_metadata() {
+        let metadata = DocumentMetadata {
+            date: Some("2024-07-01".to_string()),
+            sitename: Some("Café".to_string()),
+            ..Default::default()
+        };
+
+        // non-ASCII values cannot be sent as headers
+        assert_eq!(
+            metadata_headers(&metadata),
+            vec![("x-amz-meta-published-date".to_string(), "2024-07-01".to_string())]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+mod common;
+
+#[cfg(test)]
+mod warc_ranges_tests {
+    use pipeline::commoncrawl::unzip;
+    use pipeline::warc_ranges::{coalesce_ranges, RangeMember};
+
+    use crate::common::{entry, gzip};
+
+    #[test]
+    fn adjacent_and_nearby_records_are_merged() {
+        let entries = vec![
+            entry("a.warc.gz", 200, 50),
+            entry("a.warc.gz", 0, 100),
+            entry("a.warc.gz", 100, 100),
+        ];
+
+        let ranges = coalesce_ranges(&entries, 0, usize::MAX);
+
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].offset, ranges[0].length), (0, 250));
+        assert_eq!(
+            ranges[0].members,
+            vec![
+                RangeMember { index: 1, offset: 0, length: 100 },
+                RangeMember { index: 2, offset: 100, length: 100 },
+                RangeMember { index: 0, offset: 200, length: 50 },
+            ]
+        );
+    }
+
+    #[test]
+    fn records_are_split_by_gap_file_and_size() {
+        let entries = vec![
+            entry("a.warc.gz", 0, 100),
+            entry("a.warc.gz", 150, 100),
+            entry("a.warc.gz", 1000, 100),
+            entry("b.warc.gz", 100, 100),
+            entry("b.warc.gz", 200, 100),
+        ];
+
+        let ranges = coalesce_ranges(&entries, 50, 150);
+        let spans: Vec<_> = ranges.iter().map(|r| (r.filename.as_str(), r.offset, r.length)).collect();
+
+        assert_eq!(
+            spans,
+            vec![
+                ("a.warc.gz", 0, 100),
+                ("a.warc.gz", 150, 100),
+                ("a.warc.gz", 1000, 100),
+                ("b.warc.gz", 100, 100),
+                ("b.warc.gz", 200, 100),
+            ]
+        );
+
+        let ranges = coalesce_ranges(&entries, 50, 1000);
+        let spans: Vec<_> = ranges.iter().map(|r| (r.filename.as_str(), r.offset, r.length)).collect();
+
+        assert_eq!(
+            spans,
+            vec![("a.warc.gz", 0, 250), ("a.warc.gz", 1000, 100), ("b.warc.gz", 100, 200)]
+        );
+    }
+
+    #[test]
+    fn members_are_split_back_into_gzip_members() {
+        let first = gzip("first record");
+        let second = gzip("second record");
+        let gap = vec![0u8; 10];
+        let data = [first.clone(), gap.clone(), second.clone()].concat();
+        let entries = vec![
+            entry("a.warc.gz", 1000, first.len()),
+            entry("a.warc.gz", 1000 + first.len() + gap.len(), second.len()),
+        ];
+
+        let ranges = coalesce_ranges(&entries, gap.len(), usize::MAX);
+        assert_eq!(ranges.len(), 1);
+        let range = &ranges[0];
+
+        let records: Vec<_> = range
+            .members
+            .iter()
+            .map(|member| unzip(range.member_data(member, &data).unwrap()).unwrap())
+            .collect();
+        assert_eq!(records, vec![b"first record".to_vec(), b"second record".to_vec()]);
+
+        // a truncated response is an error rather than a panic
+        assert!(range.member_data(&range.members[1], &data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod warc_record_tests {
+    use pipeline::warc_record::{check_content_length, read_records, ResponseRecord, SkipReason};
+
+    fn record(warc_headers: &str, http: &[u8]) -> Vec<u8> {
+        let mut data = format!("WARC/1.0\r\n{}Content-Length: {}\r\n\r\n", warc_headers, http.len()).into_bytes();
+        data.extend_from_slice(http);
+        data.extend_from_slice(b"\r\n\r\n");
+        data
+    }
+
+    fn response(content_type: &str, body: &[u8]) -> Vec<u8> {
+        let mut http = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\n\r\n", content_type).into_bytes();
+        http.extend_from_slice(body);
+        http
+    }
+
+    const RESPONSE_HEADERS: &str = "WARC-Type: response\r\nWARC-Target-URI: https://example.com/\r\n";
+
+    fn read(data: &[u8]) -> Vec<Result<ResponseRecord, SkipReason>> {
+        read_records(data, None).collect()
+    }
+
+    #[test]
+    fn response_records_yield_html() {
+        let data = record(RESPONSE_HEADERS, &response("text/html; charset=utf-8", b"<p>hello</p>"));
+
+        assert_eq!(
+            read(&data),
+            vec![Ok(ResponseRecord {
+                target_uri: "https://example.com/".to_string(),
+                html: "<p>hello</p>".to_string(),
+            })]
+        );
+    }
+
+    #[test]
+    fn missing_headers_are_skipped() {
+        let http = response("text/html", b"<p>hello</p>");
+
+        let without_uri = record("WARC-Type: response\r\n", &http);
+        assert_eq!(read(&without_uri), vec![Err(SkipReason::MissingHeader("WARC-Target-URI"))]);
+
+        let without_type = record("WARC-Target-URI: https://example.com/\r\n", &http);
+        assert_eq!(read(&without_type), vec![Err(SkipReason::MissingHeader("WARC-Type"))]);
+    }
+
+    #[test]
+    fn other_record_types_are_skipped() {
+        let data = record("WARC-Type: request\r\nWARC-Target-URI: https://example.com/\r\n", b"GET / HTTP/1.1\r\n\r\n");
+
+        assert_eq!(read(&data), vec![Err(SkipReason::NotAResponse("request".to_string()))]);
+    }
+
+    #[test]
+    fn truncated_records_are_skipped() {
+        let headers = format!("{}WARC-Truncated: length\r\n", RESPONSE_HEADERS);
+        let data = record(&headers, &response("text/html", b"<p>hel"));
+
+        assert_eq!(read(&data), vec![Err(SkipReason::Truncated("length".to_string()))]);
+    }
+
+    #[test]
+    fn non_html_responses_are_skipped() {
+        let data = record(RESPONSE_HEADERS, &response("application/pdf", b"%PDF-1.4"));
+
+        assert_eq!(read(&data), vec![Err(SkipReason::NotHtml("application/pdf".to_string()))]);
+    }
+
+    #[test]
+    fn invalid_http_responses_are_skipped() {
+        let data = record(RESPONSE_HEADERS, b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n");
+
+        let records = read(&data);
+        assert_eq!(records.len(), 1);
+        assert!(matches!(records[0], Err(SkipReason::InvalidHttpResponse(_))));
+    }
+
+    #[test]
+    fn cut_off_data_ends_the_iteration() {
+        let mut data = record(RESPONSE_HEADERS, &response("text/html", b"<p>hello</p>"));
+        let mut second = record(RESPONSE_HEADERS, &response("text/html", b"<p>world</p>"));
+        second.truncate(second.len() - 20);
+        data.extend_from_slice(&second);
+
+        let records = read(&data);
+        assert_eq!(records.len(), 2);
+        assert!(records[0].is_ok());
+        assert!(matches!(records[1], Err(SkipReason::InvalidRecord(_))));
+    }
+
+    #[test]
+    fn content_length_is_checked() {
+        assert_eq!(check_content_length("short"), Err(SkipReason::TooShort(5)));
+        assert_eq!(check_content_length(&"a".repeat(500)), Ok(()));
+        assert_eq!(check_content_length(&"a".repeat(1_000_001)), Err(SkipReason::TooLong(1_000_001)));
+        assert_eq!(SkipReason::TooShort(5).label(), "too_short");
+    }
+}